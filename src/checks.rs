@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use crate::cli::DedupStrategy;
+use crate::errors::{Error, Result};
+use crate::market::Kline;
+
+/// Returns the most common spacing between consecutive `open_time`s.
+///
+/// The modal spacing is used as the expected candle interval, so a few missing candles
+/// don't skew the result. Returns `None` if there are less than two klines.
+pub fn spacing<T: Kline>(data: &[T]) -> Option<Duration> {
+    let mut counts: HashMap<Duration, usize> = HashMap::new();
+    for pair in data.windows(2) {
+        let delta = pair[1].open_time() - pair[0].open_time();
+        if delta > Duration::zero() {
+            *counts.entry(delta).or_default() += 1;
+        }
+    }
+
+    // ties are broken by the smallest spacing to favour the real interval.
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(delta, _)| delta)
+}
+
+/// Returns the fraction of consecutive `open_time` spacings differing from `expected`, `0.0` below two klines.
+pub fn irregularity<T: Kline>(data: &[T], expected: Duration) -> f64 {
+    let spacings = data.len().saturating_sub(1);
+    if spacings == 0 {
+        return 0.0;
+    }
+    let irregular = data
+        .windows(2)
+        .filter(|pair| pair[1].open_time() - pair[0].open_time() != expected)
+        .count();
+    irregular as f64 / spacings as f64
+}
+
+/// Finds the holes between consecutive klines whose spacing exceeds `expected`.
+///
+/// # Returns
+/// A vector of tuples `(before, after)` holding the `open_time` surrounding each gap.
+pub fn gaps<T: Kline>(data: &[T], expected: Duration) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    data.windows(2)
+        .map(|pair| (pair[0].open_time(), pair[1].open_time()))
+        .filter(|(before, after)| *after - *before > expected)
+        .collect()
+}
+
+/// Finds the klines breaking `low <= open, close <= high`.
+///
+/// # Returns
+/// The indexes of the inconsistent klines.
+pub fn ohlc_violations<T: Kline>(data: &[T]) -> Vec<usize> {
+    data.iter()
+        .enumerate()
+        .filter(|(_, k)| {
+            let (low, high) = (k.low(), k.high());
+            !(low <= high && (low..=high).contains(&k.open()) && (low..=high).contains(&k.close()))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Finds the `open_time`s appearing more than once.
+pub fn duplicates<T: Kline>(data: &[T]) -> Vec<DateTime<Utc>> {
+    let mut seen = HashSet::with_capacity(data.len());
+    let mut reported = HashSet::new();
+    data.iter()
+        .map(|k| k.open_time())
+        .filter(|open_time| !seen.insert(*open_time) && reported.insert(*open_time))
+        .collect()
+}
+
+/// Sorts the rows by `open_time`, keeping the order of the rows sharing it.
+///
+/// Rows whose `open_time` can't be read go first.
+///
+/// # Returns
+/// Whether the rows were out of order.
+pub fn sort_chronologically<F>(rows: &mut [Value], open_time: F) -> bool
+where
+    F: Fn(&Value) -> Option<DateTime<Utc>>,
+{
+    if rows.is_sorted_by_key(&open_time) {
+        return false;
+    }
+    rows.sort_by_cached_key(open_time);
+    true
+}
+
+/// Removes the rows sharing the same `open_time`, following `strategy`.
+///
+/// Rows whose `open_time` can't be read are kept as-is.
+///
+/// # Errors
+/// Returns `Error::ConflictingDuplicate` with the `error` strategy if two rows sharing an `open_time` differ.
+pub fn dedup<F>(rows: Vec<Value>, open_time: F, strategy: &DedupStrategy) -> Result<Vec<Value>>
+where
+    F: Fn(&Value) -> Option<DateTime<Utc>>,
+{
+    let mut positions: HashMap<DateTime<Utc>, usize> = HashMap::with_capacity(rows.len());
+    let mut deduped: Vec<Value> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let Some(time) = open_time(&row) else {
+            deduped.push(row);
+            continue;
+        };
+        match (positions.get(&time), strategy) {
+            (None, _) => {
+                positions.insert(time, deduped.len());
+                deduped.push(row);
+            }
+            (Some(_), DedupStrategy::First) => (),
+            (Some(&position), DedupStrategy::Last) => deduped[position] = row,
+            (Some(&position), DedupStrategy::Error) => {
+                if deduped[position] != row {
+                    return Err(Error::ConflictingDuplicate(time, differences(&deduped[position], &row)));
+                }
+            }
+            (Some(_), DedupStrategy::Keep) => deduped.push(row),
+        }
+    }
+    Ok(deduped)
+}
+
+/// Describes the fields of two rows which differ, by their column index or key.
+fn differences(first: &Value, second: &Value) -> String {
+    let fields: Vec<(String, Option<&Value>, Option<&Value>)> = match (first, second) {
+        (Value::Array(a), Value::Array(b)) => (0..a.len().max(b.len())).map(|i| (format!("column {i}"), a.get(i), b.get(i))).collect(),
+        (Value::Object(a), Value::Object(b)) => {
+            let keys = a.keys().chain(b.keys().filter(|key| !a.contains_key(*key)));
+            keys.map(|key| (key.clone(), a.get(key), b.get(key))).collect()
+        }
+        _ => return format!("{first} and {second}"),
+    };
+    fields
+        .into_iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(field, a, b)| {
+            let show = |value: Option<&Value>| value.map_or("nothing".to_string(), Value::to_string);
+            format!("{field} is {} and {}", show(a), show(b))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A kline opening `minute` minutes after the epoch.
+    struct Minute {
+        minute: i64,
+        ohlc: [f64; 4],
+    }
+
+    impl Kline for Minute {
+        fn open_time(&self) -> DateTime<Utc> {
+            DateTime::UNIX_EPOCH + Duration::minutes(self.minute)
+        }
+
+        fn close_time(&self) -> DateTime<Utc> {
+            self.open_time() + Duration::minutes(1) - Duration::milliseconds(1)
+        }
+
+        fn open(&self) -> f64 {
+            self.ohlc[0]
+        }
+
+        fn high(&self) -> f64 {
+            self.ohlc[1]
+        }
+
+        fn low(&self) -> f64 {
+            self.ohlc[2]
+        }
+
+        fn close(&self) -> f64 {
+            self.ohlc[3]
+        }
+
+        fn volume(&self) -> f64 {
+            1.0
+        }
+    }
+
+    fn minutes(minutes: &[i64]) -> Vec<Minute> {
+        minutes
+            .iter()
+            .map(|&minute| Minute {
+                minute,
+                ohlc: [2.0, 3.0, 1.0, 2.5],
+            })
+            .collect()
+    }
+
+    /// Reads the open time of a `[minute, value]` row.
+    fn open_time(row: &Value) -> Option<DateTime<Utc>> {
+        row[0].as_i64().map(|minute| DateTime::UNIX_EPOCH + Duration::minutes(minute))
+    }
+
+    #[test]
+    fn spacing_is_the_most_common_one() {
+        assert_eq!(spacing(&minutes(&[0, 1, 2, 5, 6])), Some(Duration::minutes(1)));
+        assert_eq!(spacing(&minutes(&[0])), None);
+    }
+
+    #[test]
+    fn gaps_are_the_spacings_over_the_expected_one() {
+        let data = minutes(&[0, 1, 2, 5, 6, 9]);
+        let at = |minute| DateTime::UNIX_EPOCH + Duration::minutes(minute);
+        assert_eq!(gaps(&data, Duration::minutes(1)), vec![(at(2), at(5)), (at(6), at(9))]);
+        assert!(gaps(&minutes(&[0, 1, 2]), Duration::minutes(1)).is_empty());
+        assert!((irregularity(&data, Duration::minutes(1)) - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn duplicates_are_reported_once() {
+        let at = |minute| DateTime::UNIX_EPOCH + Duration::minutes(minute);
+        assert_eq!(duplicates(&minutes(&[0, 1, 1, 1, 2, 2])), vec![at(1), at(2)]);
+        assert!(duplicates(&minutes(&[0, 1, 2])).is_empty());
+    }
+
+    #[test]
+    fn sort_chronologically_tells_whether_the_rows_were_out_of_order() {
+        let mut rows = vec![json!([0, "a"]), json!([2, "b"]), json!([1, "c"]), json!([2, "d"])];
+        assert!(sort_chronologically(&mut rows, open_time));
+        assert_eq!(rows, vec![json!([0, "a"]), json!([1, "c"]), json!([2, "b"]), json!([2, "d"])]);
+        assert!(!sort_chronologically(&mut rows, open_time));
+    }
+
+    #[test]
+    fn dedup_follows_each_strategy() {
+        let rows = || vec![json!([0, "a"]), json!([1, "b"]), json!([1, "c"]), json!("unreadable"), json!([2, "d"])];
+
+        let first = dedup(rows(), open_time, &DedupStrategy::First).unwrap();
+        assert_eq!(first, vec![json!([0, "a"]), json!([1, "b"]), json!("unreadable"), json!([2, "d"])]);
+
+        let last = dedup(rows(), open_time, &DedupStrategy::Last).unwrap();
+        assert_eq!(last, vec![json!([0, "a"]), json!([1, "c"]), json!("unreadable"), json!([2, "d"])]);
+
+        assert_eq!(dedup(rows(), open_time, &DedupStrategy::Keep).unwrap(), rows());
+
+        let error = dedup(rows(), open_time, &DedupStrategy::Error).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Conflicting klines at 1970-01-01 00:01:00 UTC: column 1 is "b" and "c""#
+        );
+        // identical duplicates don't conflict.
+        let identical = dedup(vec![json!([1, "b"]), json!([1, "b"])], open_time, &DedupStrategy::Error).unwrap();
+        assert_eq!(identical, vec![json!([1, "b"])]);
+    }
+
+    #[test]
+    fn only_the_differing_fields_of_a_conflict_are_reported() {
+        let (first, second) = (json!([1, "1.0", "2.0", "0.5"]), json!([1, "1.0", "2.5", "0.5", 3]));
+        assert_eq!(differences(&first, &second), r#"column 2 is "2.0" and "2.5", column 4 is nothing and 3"#);
+
+        let (first, second) = (json!({"open": 1.0, "close": 1.5}), json!({"open": 1.0, "close": 1.6}));
+        assert_eq!(differences(&first, &second), "close is 1.5 and 1.6");
+    }
+
+    #[test]
+    fn dedup_merges_overlapping_chunks() {
+        // two chunks sharing their boundary candles, as returned around an inclusive end time.
+        let chunk = |from: i64, to: i64| (from..=to).map(|minute| json!([minute, minute * 10])).collect::<Vec<_>>();
+        let rows = [chunk(0, 9), chunk(8, 19)].concat();
+        let deduped = dedup(rows, open_time, &DedupStrategy::First).unwrap();
+        assert_eq!(deduped, chunk(0, 19));
+    }
+}
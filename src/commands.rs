@@ -0,0 +1,1841 @@
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use futures::TryFutureExt;
+use futures::future::Either;
+use futures::{StreamExt, stream};
+use reqwest::{Client, Proxy, StatusCode, header::RETRY_AFTER};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Level};
+
+use crate::checks::*;
+use crate::cli::*;
+use crate::errors::*;
+use crate::indicators;
+use crate::market::*;
+use crate::progress::Progress;
+use crate::resample::{can_resample, resample};
+use crate::utils::*;
+
+/// Sent with every request, some exchanges (e.g., Coinbase) reject anonymous clients.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Cancelled on Ctrl-C, the downloads stop requesting urls and keep what they collected so far.
+static INTERRUPTED: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// Runs the command given on the command line.
+///
+/// # Errors
+/// Returns the error of the command, if any.
+pub async fn run(cli: &Cli) -> Result<()> {
+    match &cli.command {
+        Commands::Info(command) => info(command),
+        Commands::Audit(command) => audit(command),
+        Commands::Retime(command) => retime(command),
+        Commands::Convert(command) => convert(command),
+        Commands::Resample(command) => resample_file(command),
+        Commands::Indicators(command) => compute_indicators(command),
+        Commands::Doctor(command) => doctor(command).await,
+        Commands::Fetch(command) => fetch(command).await,
+    }
+}
+
+/// Fetches the klines of a symbol, as returned by the market.
+///
+/// The range is chunked, throttled, sorted and deduplicated like the `fetch` command, with its default options.
+///
+/// # Arguments
+/// * `market` - The market to fetch the klines from.
+/// * `symbol` - The trading pair symbol, in the market's own format.
+/// * `interval` - The time interval of the klines.
+/// * `from` - Start date of the range, the latest klines are fetched if both dates are omitted.
+/// * `to` - End date of the range (excluded).
+///
+/// # Errors
+/// Returns an error if the HTTP client can't be built, or `Error::PartialDownload` if some chunks failed after every retry.
+pub async fn fetch_klines(
+    market: Market,
+    symbol: &str,
+    interval: Interval,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Vec<Value>> {
+    let mut cmd = Command::new(market, symbol, interval);
+    cmd.from_date = from;
+    cmd.to_date = to;
+
+    let market = endpoint(&cmd);
+    klines(&cmd, market.as_ref(), &client(&cmd)?).await
+}
+
+/// Downloads the klines of `cmd`, sorted and deduplicated as the `fetch` command saves them.
+async fn klines(cmd: &Command, market: &dyn Endpoint<'_>, client: &Client) -> Result<Vec<Value>> {
+    let throttle = Throttle::new(std::time::Duration::from_secs_f64(1.0 / market.rate_limit()));
+    let downloaded = collect(cmd, market, client, cmd.concurrency as usize, &throttle, &Progress::Hidden).await;
+    if downloaded.failed > 0 {
+        return Err(Error::PartialDownload(downloaded.failed));
+    }
+    let open_time = |value: &Value| market.kline(value).ok().map(|kline| kline.open_time());
+    let mut rows = downloaded.rows;
+    // the chunks are collected concurrently, and their boundaries may overlap.
+    sort_chronologically(&mut rows, open_time);
+    dedup(truncate(rows, market, cmd.from_date, cmd.to_date), open_time, &cmd.dedup_strategy)
+}
+
+/// Summarizes a collection of klines.
+///
+/// The summary holds the number of elements, the start time, the end time of the klines,
+/// their interval inferred from the most common spacing, the gaps found from it,
+/// the price range with the last close, and the summed volume.
+///
+/// # Arguments
+/// * `data` - A slice of klines implementing the `Kline` trait.
+///
+/// # Errors
+/// Returns an error if the data slice is empty.
+fn summary<T: Kline>(data: &[T]) -> Result<String> {
+    let first_k = data.first().ok_or(Error::MissingData)?;
+    let last_k = data.last().ok_or(Error::MissingData)?;
+    let n = data.len();
+    let duration = last_k.close_time() - first_k.open_time();
+
+    let days = duration.num_days();
+    let hours = duration.num_hours();
+    let minutes = duration.num_minutes();
+
+    let duration = if minutes > 0 {
+        format!("{}D / {}H / {}m", separator(days, "_")?, separator(hours, "_")?, separator(minutes, "_")?)
+    } else if hours > 0 {
+        format!("{}D / {}H", separator(days, "_")?, separator(hours, "_")?)
+    } else {
+        format!("{}D", separator(days, "_")?)
+    };
+
+    let expected = spacing(data);
+    let interval = match expected {
+        Some(expected) => {
+            let name = Interval::from_duration(expected).map_or_else(|| format!("{}s", expected.num_seconds()), |interval| interval.to_string());
+            // a few differing spacings are expected gaps, more hint at missing or mixed data.
+            let irregularity = irregularity(data, expected);
+            if irregularity > 0.01 {
+                format!("{name} (irregular, {:.1}% of the spacings differ)", irregularity * 100.0)
+            } else {
+                name
+            }
+        }
+        None => "unknown".to_string(),
+    };
+
+    let gaps = expected.map(|expected| gaps(data, expected)).unwrap_or_default();
+    let gaps = match gaps.iter().max_by_key(|(before, after)| *after - *before) {
+        Some((before, after)) => format!("{}, the largest from {before} to {after}", gaps.len()),
+        None => "0".to_string(),
+    };
+
+    let low = data.iter().map(|kline| kline.low()).fold(f64::INFINITY, f64::min);
+    let high = data.iter().map(|kline| kline.high()).fold(f64::NEG_INFINITY, f64::max);
+    // the volume is only a magnitude here, the decimals would drown it.
+    let volume = separator(data.iter().map(|kline| kline.volume()).sum::<f64>().round() as i64, "_")?;
+
+    Ok(format!(
+        "
+========================
+Number of elements: {n}
+Duration: {duration}
+Interval: {interval}
+It started from {open_time},
+and ended at {close_time}.
+Gaps: {gaps}
+Price: low {low}, high {high}, last close {close}
+Volume: {volume}
+========================
+",
+        open_time = first_k.open_time(),
+        close_time = last_k.close_time(),
+        close = last_k.close()
+    ))
+}
+
+/// Displays information about JSON files containing klines.
+///
+/// This function reads each file, parses the klines, and prints information such as the number of elements,
+/// the start time, and the end time. Given several files, each summary is preceded by its path,
+/// the unreadable files are reported and skipped, and a combined total closes the output.
+///
+/// # Arguments
+/// * `cmd` - A reference to the info command configuration.
+///
+/// # Errors
+/// Returns an error if a single file is given and it cannot be read or parsed, or if no file can be.
+fn info(cmd: &InfoCommand) -> Result<()> {
+    let mut paths = Vec::new();
+    for path in &cmd.input_files {
+        if path.is_dir() {
+            paths.extend(dir_files(path)?);
+        } else {
+            paths.push(path.clone());
+        }
+    }
+    if let [path] = paths.as_slice() {
+        println!("{}", summary(&read_klines(path)?)?);
+        return Ok(());
+    }
+
+    let (mut files, mut klines, mut first, mut last) = (0, 0, None, None);
+    let mut error = None;
+    for path in &paths {
+        println!("{}", path.display());
+        match read_klines(path).and_then(|data| summary(&data).map(|summary| (data, summary))) {
+            Ok((data, summary)) => {
+                println!("{summary}");
+                files += 1;
+                klines += data.len();
+                first = first.into_iter().chain(data.iter().map(|k| k.open_time())).min();
+                last = last.into_iter().chain(data.iter().map(|k| k.close_time())).max();
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                error = Some(e);
+            }
+        }
+    }
+
+    match (first, last) {
+        (Some(first), Some(last)) => println!(
+            "Total: {} klines in {files}/{} file(s), from {first} to {last}.",
+            separator(klines, "_")?,
+            paths.len()
+        ),
+        _ => return Err(error.unwrap_or(Error::MissingData)),
+    }
+    Ok(())
+}
+
+/// Lists the files of a directory, sorted by path.
+fn dir_files(dir: &PathBuf) -> Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    Ok(paths)
+}
+
+/// Checks a collection of klines for gaps, invalid OHLC and duplicates.
+///
+/// # Returns
+/// A one-line report and whether the klines passed every check.
+fn audit_report<T: Kline>(data: &[T]) -> (String, bool) {
+    let gaps = spacing(data).map(|expected| gaps(data, expected).len()).unwrap_or_default();
+    let invalid = ohlc_violations(data).len();
+    let duplicates = duplicates(data).len();
+    let passed = gaps == 0 && invalid == 0 && duplicates == 0;
+
+    let report = format!(
+        "{n} klines, {gaps} gap(s), {invalid} invalid OHLC, {duplicates} duplicate(s)",
+        n = data.len()
+    );
+    (report, passed)
+}
+
+/// Audits every file of a directory containing klines.
+///
+/// This function prints a report per file and an overall pass/fail.
+///
+/// # Arguments
+/// * `cmd` - A reference to the audit command configuration.
+///
+/// # Errors
+/// Returns an error if the directory cannot be read or if a file fails the audit.
+fn audit(cmd: &AuditCommand) -> Result<()> {
+    let paths = dir_files(&cmd.input_dir)?;
+
+    let mut failed = 0;
+    for path in &paths {
+        let (report, passed) = match read_klines(path) {
+            Ok(data) => audit_report(&data),
+            Err(e) => (e.to_string(), false),
+        };
+        if !passed {
+            failed += 1;
+        }
+        println!("[{}] {}: {report}", if passed { "PASS" } else { "FAIL" }, path.display());
+    }
+
+    println!("{}/{} file(s) passed.", paths.len() - failed, paths.len());
+    if failed > 0 {
+        return Err(Error::AuditFailed(failed));
+    }
+
+    Ok(())
+}
+
+/// Pings the time endpoint of every supported exchange.
+///
+/// This function prints the latency and the local clock skew per exchange, and warns when the skew exceeds `--max-skew`.
+///
+/// # Arguments
+/// * `cmd` - A reference to the doctor command configuration.
+///
+/// # Errors
+/// Returns an error if an exchange is unreachable.
+async fn doctor(cmd: &DoctorCommand) -> Result<()> {
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(std::time::Duration::from_secs(cmd.timeout))
+        .build()?;
+    let max_skew = TimeDelta::milliseconds(cmd.max_skew as i64);
+
+    let mut unreachable = 0;
+    for name in Market::value_variants() {
+        // only the time endpoint is hit, the symbol and interval are placeholders.
+        let probe = Command::new(name.clone(), "BTCUSDT", Interval::M1);
+        let market = endpoint(&probe);
+
+        match clock_skew(&client, market.as_ref()).await {
+            Ok((latency, skew)) => {
+                println!(
+                    "[OK] {name}: latency {} ms, clock skew {:+} ms",
+                    latency.num_milliseconds(),
+                    skew.num_milliseconds()
+                );
+                if skew.abs() > max_skew {
+                    eprintln!("Warning: the local clock is off by more than {} ms from {name}.", cmd.max_skew);
+                }
+            }
+            Err(e) => {
+                unreachable += 1;
+                println!("[FAIL] {name}: {e}");
+            }
+        }
+    }
+
+    if unreachable > 0 {
+        return Err(Error::Unreachable(unreachable));
+    }
+
+    Ok(())
+}
+
+/// Measures the round trip to the market time endpoint, and how far the local clock is ahead of the server one.
+///
+/// # Returns
+/// The latency and the clock skew.
+async fn clock_skew(client: &Client, market: &dyn Endpoint<'_>) -> Result<(TimeDelta, TimeDelta)> {
+    let sent = Utc::now();
+    let server = server_time(client, market).await?;
+    let received = Utc::now();
+    // the server time is read roughly halfway through the round trip.
+    Ok((received - sent, sent + (received - sent) / 2 - server))
+}
+
+/// Converts the timestamps of a JSON file between seconds and milliseconds.
+///
+/// The timestamp columns are found from the row layout, and their type (number or string) is kept.
+///
+/// # Arguments
+/// * `cmd` - A reference to the retime command configuration.
+///
+/// # Errors
+/// Returns an error if the file isn't made of known array-based klines.
+fn retime(cmd: &RetimeCommand) -> Result<()> {
+    let mut rows = read_data_from_file::<Vec<Value>>(&cmd.input_file)?;
+    let Some(first) = rows.first() else {
+        return Err(Error::MissingData);
+    };
+    let layout = ArrayLayout::detect(first).ok_or(Error::InvalidFile)?;
+    let columns = layout.timestamp_columns();
+    let from = match cmd.from {
+        Some(unit) => unit,
+        None => TimeUnit::detect(integer(first, layout.open_time)?),
+    };
+
+    if from != cmd.to {
+        for row in &mut rows {
+            for &index in &columns {
+                let timestamp = integer(row, index)?;
+                let timestamp = match cmd.to {
+                    TimeUnit::Milliseconds => timestamp * 1000,
+                    TimeUnit::Seconds => timestamp / 1000,
+                };
+                row[index] = match row[index] {
+                    Value::String(_) => Value::from(timestamp.to_string()),
+                    _ => Value::from(timestamp),
+                };
+            }
+        }
+    }
+
+    write_to_file(cmd.output_file.as_ref().unwrap_or(&cmd.input_file), &rows)
+}
+
+/// Converts a kline file to another format.
+///
+/// The json and ndjson formats keep the rows as they are, the other ones are written from the parsed klines.
+///
+/// # Arguments
+/// * `cmd` - A reference to the convert command configuration.
+///
+/// # Errors
+/// Returns `Error::InvalidFile` if the file doesn't match any known kline format,
+/// or `Error::UnknownInterval` if a sqlite interval can't be inferred.
+fn convert(cmd: &ConvertCommand) -> Result<()> {
+    let klines = read_klines(&cmd.input_file)?;
+    match cmd.format {
+        OutputFormat::Json => write_to_file(&cmd.output_file, &read_data_from_file::<Value>(&cmd.input_file)?),
+        OutputFormat::Ndjson => write_ndjson(&cmd.output_file, &read_data_from_file::<Value>(&cmd.input_file)?),
+        OutputFormat::Csv => write_csv(&cmd.output_file, &klines),
+        OutputFormat::Parquet => write_parquet(&cmd.output_file, &klines),
+        OutputFormat::Sqlite => {
+            let interval = cmd
+                .interval
+                .clone()
+                .or_else(|| spacing(&klines).and_then(Interval::from_duration))
+                .ok_or(Error::UnknownInterval)?;
+            // the symbol is required by clap along with the sqlite format.
+            write_sqlite(&cmd.output_file, cmd.symbol.as_deref().unwrap_or_default(), &interval, &klines)
+        }
+    }
+}
+
+/// Resamples the klines of a file to a coarser interval.
+///
+/// Each candle opens with the first kline of its bucket and closes with the last one, the high and low being
+/// the extremes of the bucket and the volume and trades its sums.
+///
+/// # Arguments
+/// * `cmd` - A reference to the resample command configuration.
+///
+/// # Errors
+/// Returns `Error::InvalidResample` if the interval isn't a multiple of the klines spacing, e.g., when upsampling.
+fn resample_file(cmd: &ResampleCommand) -> Result<()> {
+    let mut data = read_klines(&cmd.input_file)?;
+    data.sort_by_key(|kline| kline.open_time());
+    let spacing = spacing(&data).ok_or(Error::MissingData)?;
+    if !can_resample(spacing, &cmd.interval) {
+        return Err(Error::InvalidResample(spacing.num_seconds(), cmd.interval.clone()));
+    }
+    write_to_file(&cmd.output_file, &resample(&data, &cmd.interval, Utc::now()))
+}
+
+/// Computes indicators on the close prices of a JSON file.
+///
+/// The klines are saved in the unified format, with a column per indicator (e.g., `sma_20`)
+/// which is `null` until enough klines are available.
+///
+/// # Arguments
+/// * `cmd` - A reference to the indicators command configuration.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or the output cannot be written.
+fn compute_indicators(cmd: &IndicatorsCommand) -> Result<()> {
+    let data = read_klines(&cmd.input_file)?;
+    let closes = data.iter().map(|kline| kline.close()).collect::<Vec<_>>();
+
+    let mut columns = Vec::new();
+    for (name, periods, indicator) in [
+        ("sma", &cmd.sma, indicators::sma as fn(&[f64], usize) -> Vec<Option<f64>>),
+        ("ema", &cmd.ema, indicators::ema),
+        ("rsi", &cmd.rsi, indicators::rsi),
+    ] {
+        for &period in periods {
+            columns.push((format!("{name}_{period}"), indicator(&closes, period as usize)));
+        }
+    }
+
+    let now = Utc::now();
+    let rows = data
+        .iter()
+        .enumerate()
+        .map(|(i, kline)| {
+            let mut row = serde_json::to_value(Candle::new(kline, now))?;
+            if let Value::Object(fields) = &mut row {
+                for (name, values) in &columns {
+                    fields.insert(name.clone(), values[i].into());
+                }
+            }
+            Ok(row)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    write_to_file(&cmd.output_file, &rows)
+}
+
+/// Fetches the market server time.
+async fn server_time(client: &Client, market: &dyn Endpoint<'_>) -> Result<DateTime<Utc>> {
+    let response = client.get(market.time_url()).send().await?;
+    market.server_time(&response.json::<Value>().await?)
+}
+
+/// The maximum number of in-flight requests, and whether their rows are collected in order.
+#[derive(Clone, Copy)]
+enum Concurrency {
+    /// The rows are collected in the order of the urls.
+    Ordered(usize),
+    /// The rows are collected as the urls complete.
+    Unordered(usize),
+}
+
+/// The rows of the downloaded urls, the failed urls being reported and skipped.
+#[derive(Default)]
+struct Downloaded {
+    rows: Vec<Value>,
+    /// Number of urls downloaded.
+    succeeded: usize,
+    /// Number of urls given up on.
+    failed: usize,
+}
+
+/// Downloads every url of the market, following its cursor if paginated.
+async fn collect(
+    cmd: &Command,
+    market: &dyn Endpoint<'_>,
+    client: &Client,
+    concurrency: usize,
+    throttle: &Throttle,
+    progress: &Progress,
+) -> Downloaded {
+    let concurrency = if cmd.unordered {
+        Concurrency::Unordered(concurrency)
+    } else {
+        Concurrency::Ordered(concurrency)
+    };
+    let downloaded = download(client, market, &market.urls(), concurrency, cmd.retry_counter, throttle, progress).await;
+    // cursor-paginated markets only know the next url once the previous page is downloaded.
+    paginate(client, market, downloaded, cmd.retry_counter, throttle, progress).await
+}
+
+/// Drops the rows opening outside of `[from, to)`, rows that can't be parsed are kept.
+///
+/// Chunk boundaries and exchange inclusivity may return candles outside the range.
+fn truncate(mut rows: Vec<Value>, market: &dyn Endpoint<'_>, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<Value> {
+    rows.retain(|value| match market.kline(value) {
+        Ok(kline) => {
+            let open_time = kline.open_time();
+            from.is_none_or(|from| open_time >= from) && to.is_none_or(|to| open_time < to)
+        }
+        Err(_) => true,
+    });
+    rows
+}
+
+/// Follows the cursor of a paginated market, until it runs out of pages.
+///
+/// The pages are walked backward in time, so they are put back in chronological order.
+///
+/// # Arguments
+/// * `first_page` - The download of the market urls.
+/// * `attempts` - The maximum number of requests per page.
+///
+/// # Returns
+/// The rows of every page, `first_page` as is for markets without a cursor.
+async fn paginate(
+    client: &Client,
+    market: &dyn Endpoint<'_>,
+    first_page: Downloaded,
+    attempts: u8,
+    throttle: &Throttle,
+    progress: &Progress,
+) -> Downloaded {
+    let (mut succeeded, mut failed) = (first_page.succeeded, first_page.failed);
+    let mut collected = first_page.rows.len();
+    let mut pages = vec![first_page.rows];
+    while !INTERRUPTED.is_cancelled()
+        && let Some(url) = pages.last().and_then(|page| market.next_url(page, collected))
+    {
+        let page = download(client, market, &[url], Concurrency::Ordered(1), attempts, throttle, &Progress::Hidden).await;
+        collected += page.rows.len();
+        succeeded += page.succeeded;
+        failed += page.failed;
+        if page.succeeded > 0 {
+            progress.chunk_done(page.rows.len());
+        }
+        pages.push(page.rows);
+    }
+    Downloaded {
+        rows: pages.into_iter().rev().flatten().collect(),
+        succeeded,
+        failed,
+    }
+}
+
+/// Requests a url and extracts its rows, along with the response status.
+///
+/// # Errors
+/// Returns `Error::Http` on 429 (Too Many Requests) and 5xx statuses, which are worth retrying,
+/// along with the delay of the `Retry-After` header, if any.
+///
+/// The `throttle` is paused when the market headers tell its quota is nearly used up.
+async fn request_rows(client: &Client, market: &dyn Endpoint<'_>, url: &str, throttle: &Throttle) -> Result<(StatusCode, Vec<Value>)> {
+    // the url is already part of the `Error::Request` message.
+    let response = client.get(url).send().map_err(|e| Error::from(e.without_url())).await?;
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        let retry_after = response.headers().get(RETRY_AFTER).and_then(|value| retry_after(value.to_str().ok()?));
+        return Err(Error::Http { status, retry_after });
+    }
+    if let Some(pause) = market.throttle_from_headers(response.headers()) {
+        throttle.pause(pause).await;
+    }
+    Ok((status, market.rows(read_json::<Value>(response).await?)?))
+}
+
+/// Downloads the rows of a url, retrying it after 1s, 2s, 4s... until `attempts` is reached.
+///
+/// # Errors
+/// Returns `Error::Request`, holding the url, once the url failed with a permanent error or `attempts` times.
+async fn download_url(client: &Client, market: &dyn Endpoint<'_>, url: &str, attempts: u8, throttle: &Throttle) -> Result<Vec<Value>> {
+    let mut attempt = 1;
+    let started = std::time::Instant::now();
+    loop {
+        throttle.wait().await;
+        let result = request_rows(client, market, url, throttle).await;
+        let (retries, duration_ms) = (attempt - 1, started.elapsed().as_millis() as u64);
+        match result {
+            Ok((status, rows)) => {
+                tracing::info!(
+                    url,
+                    status = status.as_u16(),
+                    candles = rows.len(),
+                    retries,
+                    duration_ms,
+                    "chunk downloaded"
+                );
+                return Ok(rows);
+            }
+            Err(e) if e.is_retryable() && attempt < attempts => {
+                tracing::info!(url, error = %e, retries, duration_ms, "chunk retried");
+                // the exchange backpressure prevails over the exponential backoff.
+                let backoff = std::time::Duration::from_secs(1 << (attempt - 1));
+                tokio::time::sleep(e.retry_after().unwrap_or(backoff)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                tracing::info!(url, error = %e, retries, duration_ms, "chunk failed");
+                return Err(Error::Request {
+                    url: url.to_string(),
+                    source: Box::new(e),
+                });
+            }
+        }
+    }
+}
+
+/// Downloads every url and collects the returned rows.
+///
+/// A url failing with a retryable error is requested again after 1s, 2s, 4s... until `attempts` is reached.
+///
+/// # Arguments
+/// * `client` - The HTTP client used for the requests.
+/// * `market` - The market extracting the rows of each response.
+/// * `urls` - The urls to download, in chronological order.
+/// * `concurrency` - The maximum number of in-flight requests, and whether the rows are collected in order.
+/// * `attempts` - The maximum number of requests per url.
+/// * `throttle` - The throttle spacing out the requests.
+/// * `progress` - The reporter of each downloaded url.
+async fn download(
+    client: &Client,
+    market: &dyn Endpoint<'_>,
+    urls: &[String],
+    concurrency: Concurrency,
+    attempts: u8,
+    throttle: &Throttle,
+    progress: &Progress,
+) -> Downloaded {
+    let rows_stream = stream::iter(urls).map(|url| download_url(client, market, url, attempts, throttle));
+    let rows_stream = match concurrency {
+        Concurrency::Ordered(n) => Either::Left(rows_stream.buffered(n)),
+        Concurrency::Unordered(n) => Either::Right(rows_stream.buffer_unordered(n)),
+    }
+    .take_until(INTERRUPTED.cancelled());
+
+    let downloaded = Downloaded {
+        rows: Vec::with_capacity(urls.len() * 1000),
+        ..Default::default()
+    };
+    rows_stream
+        .fold(downloaded, |mut downloaded, result| async move {
+            match result {
+                Ok(rows) => {
+                    progress.chunk_done(rows.len());
+                    downloaded.rows.extend(rows);
+                    downloaded.succeeded += 1;
+                }
+                Err(e) => {
+                    progress.chunk_failed(&e);
+                    downloaded.failed += 1;
+                }
+            }
+            downloaded
+        })
+        .await
+}
+
+/// Merges `rows` after the ones saved in `path`, the new rows replacing the saved ones of the same open time.
+///
+/// # Errors
+/// Returns `Error::IncompatibleFile` if a saved row has no open time for `open_time`, i.e., it's another kline format.
+fn append<F>(path: &PathBuf, rows: Vec<Value>, open_time: F) -> Result<Vec<Value>>
+where
+    F: Fn(&Value) -> Option<DateTime<Utc>>,
+{
+    if !path.exists() {
+        return Ok(rows);
+    }
+    let saved = read_data_from_file::<Value>(path)?;
+    if saved.iter().any(|row| open_time(row).is_none()) {
+        return Err(Error::IncompatibleFile(path.clone()));
+    }
+    let mut merged = dedup(saved.into_iter().chain(rows).collect(), &open_time, &DedupStrategy::Last)?;
+    merged.sort_by_key(|row| open_time(row));
+    Ok(merged)
+}
+
+/// Converts the rows returned by the market into exchange-agnostic candles.
+///
+/// # Errors
+/// Returns an error if a row doesn't match the market kline format.
+fn unify(cmd: &Command, market: &dyn Endpoint, rows: &[Value]) -> Result<Vec<Candle>> {
+    let now = Utc::now();
+    rows.iter()
+        .map(|value| {
+            market.kline(value).map(|kline| {
+                let mut candle = Candle::new(&kline, now);
+                if cmd.with_vwap {
+                    candle = candle.with_vwap(&kline);
+                }
+                if cmd.with_net_taker {
+                    candle = candle.with_net_taker(&kline);
+                }
+                candle
+            })
+        })
+        .collect()
+}
+
+/// Fetches klines data from the specified exchange, for every symbol of the command.
+///
+/// # Arguments
+/// * `cmd` - A reference to the command configuration.
+///
+/// # Errors
+/// Returns an error if the fetch operation fails.
+async fn fetch(cmd: &Command) -> Result<()> {
+    init_logs(cmd);
+    let commands = cmd.per_symbol()?;
+    // the commands actually downloading urls, one per market of each symbol with `--markets`.
+    let downloads = commands
+        .iter()
+        .flat_map(|cmd| if cmd.markets.is_empty() { vec![cmd.clone()] } else { cmd.per_market() })
+        .collect::<Vec<_>>();
+    if cmd.dry_run || cmd.plan_stats || cmd.estimate_size {
+        return plans(&downloads, std::io::stdout().lock());
+    }
+
+    let (mut urls, mut candles) = (0, 0);
+    for cmd in &downloads {
+        let market = endpoint(cmd);
+        let chunks = market.chunks();
+        candles += estimate_candles(cmd, market.as_ref(), chunks);
+        urls += chunks;
+    }
+    // checked before any request, the plans above being the way to look at a huge download.
+    if urls > cmd.max_chunks && !cmd.force {
+        return Err(Error::TooManyChunks(urls));
+    }
+    let progress = Progress::build(cmd, urls, candles)?;
+
+    let client = client(cmd)?;
+    // the symbols share the market rate limit.
+    let throttle = throttle(cmd);
+
+    let mut reports = Vec::with_capacity(commands.len());
+    // each market checks its own symbol with `--markets`.
+    if !cmd.no_validate_symbol && cmd.markets.is_empty() {
+        check_symbols(&client, endpoint(cmd).as_ref(), &cmd.symbols).await?;
+    }
+
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            INTERRUPTED.cancel();
+            // a second Ctrl-C doesn't wait for the klines to be saved.
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    for cmd in &commands {
+        let span = tracing::info_span!("fetch", symbol = %cmd.symbol, market = %cmd.market, interval = %cmd.interval);
+        let report = if cmd.markets.is_empty() {
+            fetch_symbol(cmd, endpoint(cmd).as_ref(), &client, &throttle, &progress)
+                .instrument(span)
+                .await?
+        } else {
+            fetch_merged(cmd, &client, &progress, endpoint).instrument(span).await?
+        };
+        reports.push(report);
+        // the next symbols aren't started, the interrupted one is saved as far as it went.
+        if INTERRUPTED.is_cancelled() {
+            break;
+        }
+    }
+
+    if !cmd.stdout && !cmd.quiet {
+        let status = if INTERRUPTED.is_cancelled() {
+            "Download ticks interrupted, the klines downloaded so far were saved."
+        } else {
+            "Download ticks done."
+        };
+        progress.finish(&format!("{status}\n{}", reports.join("\n")));
+    }
+
+    Ok(())
+}
+
+/// Installs the logger selected by `--log-format`, writing to stderr.
+///
+/// The text logs only show the warnings, the JSON ones add an event per chunk (url, status, candles, retries,
+/// duration) within the span of its symbol. Nothing is logged with `--quiet`.
+fn init_logs(cmd: &Command) {
+    if cmd.quiet {
+        return;
+    }
+    let logs = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    // a logger installed by an embedding binary is left in place.
+    let _ = match cmd.log_format {
+        LogFormat::Text => logs.with_max_level(Level::WARN).without_time().with_target(false).try_init(),
+        LogFormat::Json => logs.with_max_level(Level::INFO).json().try_init(),
+    };
+}
+
+/// Builds the HTTP client of the command, with its timeout, compression and proxy.
+fn client(cmd: &Command) -> Result<Client> {
+    let mut client = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(std::time::Duration::from_secs(cmd.timeout))
+        .gzip(cmd.response_compression)
+        .brotli(cmd.response_compression)
+        .deflate(cmd.response_compression);
+    if let Some(proxy) = &cmd.proxy {
+        client = client.proxy(Proxy::all(proxy)?);
+    }
+    Ok(client.build()?)
+}
+
+/// Builds the throttle spacing out the requests to the command market.
+fn throttle(cmd: &Command) -> Throttle {
+    let rate = cmd.max_requests_per_second.unwrap_or(endpoint(cmd).rate_limit());
+    Throttle::new(std::time::Duration::from_secs_f64(1.0 / rate).max(std::time::Duration::from_millis(cmd.request_delay)))
+}
+
+/// Fetches the klines of a single symbol from every `--markets` market, and merges them by open time.
+///
+/// The candles of the first market prevail, the next markets only filling its gaps,
+/// so a flaky market is made up for by the other ones. The endpoint of each market is built by `endpoint`.
+///
+/// # Returns
+/// A one-line report of the candles saved, and how many came from each market.
+///
+/// # Errors
+/// Returns `Error::PartialDownload` if every market failed on some urls, unless `--allow-partial` is given.
+async fn fetch_merged<E>(cmd: &Command, client: &Client, progress: &Progress, endpoint: E) -> Result<String>
+where
+    E: Fn(&Command) -> Box<dyn Endpoint<'_> + '_>,
+{
+    let concurrency = if cmd.deterministic { 1 } else { cmd.concurrency as usize };
+    let mut merged = BTreeMap::new();
+    let (mut sources, mut failed) = (Vec::new(), Vec::new());
+    for cmd in cmd.per_market() {
+        let market = endpoint(&cmd);
+        let market = market.as_ref();
+        if !cmd.no_validate_symbol {
+            check_symbols(client, market, std::slice::from_ref(&cmd.symbol)).await?;
+        }
+        if cmd.verbose
+            && !cmd.stdout
+            && let Some(url) = market.urls().first()
+        {
+            println!("{url}");
+        }
+        // each market has its own rate limit.
+        let downloaded = collect(&cmd, market, client, concurrency, &throttle(&cmd), progress).await;
+        let mut rows = downloaded.rows;
+        if !cmd.no_truncate {
+            rows = truncate(rows, market, cmd.from_date, cmd.to_date);
+        }
+        // the last candles count the closed ones only, as for a single market and by the same clock.
+        if cmd.only_closed || cmd.last.is_some() {
+            let now = server_time(client, market).await?;
+            rows.retain(|value| market.kline(value).is_ok_and(|kline| kline.is_closed(now)));
+        }
+        let before = merged.len();
+        for candle in unify(&cmd, market, &rows)? {
+            merged.entry(candle.open_time()).or_insert(candle);
+        }
+        sources.push(format!("{} from {}", separator(merged.len() - before, "_")?, cmd.market));
+        failed.push(downloaded.failed);
+    }
+    // a gap left by a market is only a problem if no other market filled it, which can't be told apart here.
+    if failed.iter().all(|&failed| failed > 0) && !cmd.allow_partial {
+        return Err(Error::PartialDownload(failed.iter().sum()));
+    }
+
+    let mut candles = merged.into_values().collect::<Vec<_>>();
+    if let Some(last) = cmd.last.or(cmd.count) {
+        candles.drain(..candles.len().saturating_sub(last as usize));
+    }
+    if cmd.validate {
+        warn_ohlc_violations(&candles);
+    }
+    if cmd.reverse {
+        candles.reverse();
+    }
+
+    let coverage = match (candles.iter().map(|c| c.open_time()).min(), candles.iter().map(|c| c.open_time()).max()) {
+        (Some(first), Some(last)) => format!("{} candles from {first} to {last}", separator(candles.len(), "_")?),
+        _ => "no candles".to_string(),
+    };
+    let report = format!("{}: {coverage}, {}.", cmd.symbol, sources.join(", "));
+
+    if let Some(filepath) = &cmd.output_file {
+        match cmd.format {
+            OutputFormat::Csv => write_csv(filepath, &candles)?,
+            OutputFormat::Parquet => write_parquet(filepath, &candles)?,
+            OutputFormat::Sqlite => write_sqlite(filepath, &cmd.symbol, &cmd.interval, &candles)?,
+            OutputFormat::Json => write_to_file(filepath, &candles)?,
+            OutputFormat::Ndjson => write_ndjson(filepath, &candles)?,
+        }
+    }
+    if cmd.stdout {
+        write_stdout(std::io::stdout().lock(), &cmd.stdout_format()?, &candles)?;
+    }
+
+    Ok(report)
+}
+
+/// Warns about every kline whose open and close aren't within its low and high.
+fn warn_ohlc_violations<T: Kline>(klines: &[T]) {
+    for index in ohlc_violations(klines) {
+        let kline = &klines[index];
+        tracing::warn!(
+            "invalid OHLC at #{index} ({}): open {}, high {}, low {}, close {}",
+            kline.open_time(),
+            kline.open(),
+            kline.high(),
+            kline.low(),
+            kline.close()
+        );
+    }
+}
+
+/// Checks the symbols are traded on the market, for the markets listing them.
+///
+/// # Errors
+/// Returns `Error::InvalidSymbol`, with the closest listed symbol, for the first unknown symbol.
+async fn check_symbols(client: &Client, market: &dyn Endpoint<'_>, symbols: &[String]) -> Result<()> {
+    let Some(url) = market.symbols_url() else {
+        return Ok(());
+    };
+    let listed = market.symbols(&client.get(url).send().await?.error_for_status()?.json::<Value>().await?)?;
+    match symbols
+        .iter()
+        .find(|symbol| !listed.iter().any(|listed| listed.eq_ignore_ascii_case(symbol)))
+    {
+        Some(symbol) => Err(Error::InvalidSymbol(match closest(symbol, &listed) {
+            Some(suggestion) => format!("{symbol}, did you mean {suggestion}?"),
+            None => symbol.clone(),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Estimates the number of candles the `chunks` requests of a single symbol command return.
+fn estimate_candles(cmd: &Command, market: &dyn Endpoint<'_>, chunks: usize) -> u64 {
+    // every request returns at most `limit` candles, the range may narrow it down.
+    let max_candles = chunks as u64 * market.limit() as u64;
+    cmd.last
+        .or(cmd.candles_in_range())
+        .map_or(max_candles, |candles| candles.min(max_candles))
+}
+
+/// Writes the summary of every command to `out`, stopping quietly once it's closed (e.g., piped into `head`).
+fn plans(commands: &[Command], mut out: impl Write) -> Result<()> {
+    match commands.iter().try_for_each(|cmd| plan(cmd, &mut out)) {
+        Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Writes the `--dry-run`, `--plan-stats` or `--estimate-size` summary of a single symbol command.
+fn plan(cmd: &Command, out: &mut impl Write) -> Result<()> {
+    let market = endpoint(cmd);
+    let urls = market.urls();
+    let chunks = market.chunks();
+    let candles = estimate_candles(cmd, market.as_ref(), chunks);
+
+    if cmd.dry_run {
+        for url in &urls {
+            writeln!(out, "{url}")?;
+        }
+        // the requests are spaced out by the throttle, whatever the concurrency.
+        let rate = cmd.max_requests_per_second.unwrap_or(market.rate_limit());
+        let spacing = (1.0 / rate).max(cmd.request_delay as f64 / 1000.0);
+        writeln!(
+            out,
+            "{} url(s) for {}, ~{:.1}s of requests at most {rate} per second.",
+            separator(chunks, "_")?,
+            cmd.symbol,
+            chunks as f64 * spacing
+        )?;
+    } else if cmd.plan_stats {
+        let coverage = match (cmd.from_date, cmd.to_date) {
+            (Some(from), Some(to)) => format!("{from}..{to}"),
+            (Some(from), None) => format!("{from}..now"),
+            (None, Some(to)) => format!("..{to}"),
+            (None, None) => "the latest candles".to_string(),
+        };
+        writeln!(
+            out,
+            "{} chunk(s), ~{} candles, covering {coverage} of {} at {} on {}.",
+            separator(chunks, "_")?,
+            separator(candles, "_")?,
+            cmd.symbol,
+            cmd.interval,
+            cmd.market
+        )?;
+    } else {
+        // Parquet and SQLite store typed columns, whatever the market rows look like.
+        let row_size = match cmd.format {
+            OutputFormat::Parquet => Candle::PARQUET_ROW_SIZE,
+            OutputFormat::Sqlite => Candle::SQLITE_ROW_SIZE,
+            OutputFormat::Csv => Candle::ROW_SIZE,
+            OutputFormat::Json | OutputFormat::Ndjson if cmd.unified => Candle::ROW_SIZE,
+            OutputFormat::Json | OutputFormat::Ndjson => market.row_size(),
+        };
+        writeln!(
+            out,
+            "Estimated output size: ~{} for ~{} {} candles.",
+            format_size(candles * row_size as u64),
+            separator(candles, "_")?,
+            cmd.symbol
+        )?;
+    }
+    Ok(())
+}
+
+/// Fetches and saves the klines of a single symbol command.
+///
+/// # Returns
+/// The report of the download: the candles collected, their time coverage and the urls downloaded.
+///
+/// # Errors
+/// Returns an error if the klines can't be checked or saved.
+async fn fetch_symbol(cmd: &Command, market: &dyn Endpoint<'_>, client: &Client, throttle: &Throttle, progress: &Progress) -> Result<String> {
+    let urls = market.urls();
+
+    if cmd.verbose
+        && !cmd.stdout
+        && let Some(url) = urls.first()
+    {
+        println!("{url}");
+    }
+
+    let concurrency = if cmd.deterministic { 1 } else { cmd.concurrency as usize };
+    let downloaded = collect(cmd, market, client, concurrency, throttle, progress).await;
+    // the failed urls are already reported, nothing is saved with gaps unless allowed.
+    if downloaded.failed > 0 && !cmd.allow_partial {
+        return Err(Error::PartialDownload(downloaded.failed));
+    }
+    let mut all_klines = downloaded.rows;
+    // the markets return chronological rows, a reordering hints at an exchange hiccup worth knowing about,
+    // unless the chunks were collected as they completed.
+    if sort_chronologically(&mut all_klines, |value| market.kline(value).ok().map(|k| k.open_time())) && !cmd.unordered && !cmd.quiet {
+        tracing::warn!("the {} klines were returned out of order, they were sorted by open time", cmd.symbol);
+    }
+
+    if cmd.only_closed || cmd.last.is_some() {
+        let mut now = server_time(client, market).await?;
+        let forming = all_klines
+            .last()
+            .and_then(|value| market.kline(value).ok())
+            .filter(|kline| !kline.is_closed(now));
+        if let Some(kline) = forming
+            && cmd.reprobe
+            && let Some(last_url) = urls.last()
+        {
+            let closes_in = kline.open_time() + cmd.interval.duration() - now;
+            if closes_in <= chrono::Duration::seconds(10) {
+                tokio::time::sleep(closes_in.to_std().unwrap_or_default() + std::time::Duration::from_secs(1)).await;
+                let rows = download(
+                    client,
+                    market,
+                    std::slice::from_ref(last_url),
+                    Concurrency::Ordered(1),
+                    cmd.retry_counter,
+                    throttle,
+                    &Progress::Hidden,
+                )
+                .await
+                .rows;
+                let is_newer = |value: &Value| market.kline(value).is_ok_and(|k| k.open_time() >= kline.open_time());
+                all_klines.retain(|value| !is_newer(value));
+                all_klines.extend(rows.into_iter().filter(is_newer));
+                now = server_time(client, market).await?;
+            }
+        }
+        all_klines.retain(|value| market.kline(value).is_ok_and(|kline| kline.is_closed(now)));
+    }
+
+    // the range of `--count` may open mid-candle, leaving one candle too many as well.
+    if let Some(last) = cmd.last.or(cmd.count) {
+        // the last candle may have been closed already, leaving one candle too many.
+        let extra = all_klines.len().saturating_sub(last as usize);
+        all_klines.drain(..extra);
+    }
+
+    if !cmd.no_truncate {
+        all_klines = truncate(all_klines, market, cmd.from_date, cmd.to_date);
+    }
+
+    all_klines = dedup(all_klines, |value| market.kline(value).ok().map(|k| k.open_time()), &cmd.dedup_strategy)?;
+
+    if cmd.reverse {
+        all_klines.reverse();
+    }
+
+    let open_times = all_klines
+        .iter()
+        .filter_map(|value| market.kline(value).ok().map(|kline| kline.open_time()));
+    let coverage = match (open_times.clone().min(), open_times.max()) {
+        (Some(first), Some(last)) => format!("{} candles from {first} to {last}", separator(all_klines.len(), "_")?),
+        _ => "no candles".to_string(),
+    };
+    let report = format!(
+        "{}: {coverage}, {}/{} url(s) downloaded.",
+        cmd.symbol,
+        downloaded.succeeded,
+        downloaded.succeeded + downloaded.failed
+    );
+
+    if cmd.validate {
+        let klines = all_klines.iter().map(|value| market.kline(value)).collect::<Result<Vec<_>>>()?;
+        warn_ohlc_violations(&klines);
+    }
+
+    if let Some(filepath) = &cmd.output_file {
+        match cmd.format {
+            OutputFormat::Csv => write_csv(filepath, &unify(cmd, market, &all_klines)?)?,
+            OutputFormat::Parquet => {
+                let klines = all_klines.iter().map(|row| market.kline(row)).collect::<Result<Vec<_>>>()?;
+                write_parquet(filepath, &klines)?
+            }
+            OutputFormat::Sqlite => {
+                let klines = all_klines.iter().map(|row| market.kline(row)).collect::<Result<Vec<_>>>()?;
+                write_sqlite(filepath, &cmd.symbol, &cmd.interval, &klines)?
+            }
+            OutputFormat::Json if cmd.append => {
+                let merged = if cmd.unified {
+                    let candles = unify(cmd, market, &all_klines)?;
+                    let rows = candles.iter().map(serde_json::to_value).collect::<serde_json::Result<Vec<_>>>()?;
+                    append(filepath, rows, |row| Candle::deserialize(row).ok().map(|candle| candle.open_time()))?
+                } else {
+                    append(filepath, all_klines.clone(), |row| market.kline(row).ok().map(|kline| kline.open_time()))?
+                };
+                write_to_file(filepath, &merged)?
+            }
+            OutputFormat::Json if cmd.unified => write_to_file(filepath, &unify(cmd, market, &all_klines)?)?,
+            OutputFormat::Json => write_to_file(filepath, &all_klines)?,
+            OutputFormat::Ndjson if cmd.unified => write_ndjson(filepath, &unify(cmd, market, &all_klines)?)?,
+            OutputFormat::Ndjson => write_ndjson(filepath, &all_klines)?,
+        }
+
+        if cmd.with_funding {
+            let funding_urls = market.funding_urls().ok_or(Error::NoFunding)?;
+            let funding = download(
+                client,
+                market,
+                &funding_urls,
+                Concurrency::Ordered(concurrency),
+                cmd.retry_counter,
+                throttle,
+                &Progress::Hidden,
+            )
+            .await;
+            if funding.failed > 0 && !cmd.allow_partial {
+                return Err(Error::PartialDownload(funding.failed));
+            }
+            // the funding rates are saved as JSON, whatever the format of the klines.
+            write_to_file(&sibling_path(filepath, "funding", "json"), &funding.rows)?;
+        }
+    }
+
+    if cmd.stdout {
+        let stdout = std::io::stdout().lock();
+        if cmd.unified {
+            write_stdout(stdout, &cmd.stdout_format()?, &unify(cmd, market, &all_klines)?)?;
+        } else {
+            write_stdout(stdout, &cmd.stdout_format()?, &all_klines)?;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    use chrono::{DurationRound, TimeZone};
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{MockServer, Reply, Request, scratch_dir};
+
+    /// A Binance row of the minute opening at `open_time` (ms).
+    fn row(open_time: i64) -> Value {
+        json!([
+            open_time,
+            "1.0",
+            "2.0",
+            "0.5",
+            "1.5",
+            "10.0",
+            open_time + 59_999,
+            "15.0",
+            3,
+            "5.0",
+            "7.5",
+            "0"
+        ])
+    }
+
+    /// The integer parameter `key` of the query of `path`.
+    fn query(path: &str, key: &str) -> Option<i64> {
+        let (_, query) = path.split_once('?')?;
+        query.split('&').find_map(|pair| pair.strip_prefix(key)?.strip_prefix('=')?.parse().ok())
+    }
+
+    /// Answers like Binance: minute klines from `startTime` (through `endTime`), or the latest ones,
+    /// the server time on `/time`, and funding rates on `/funding`.
+    fn exchange(request: &Request) -> Reply {
+        let now = Utc::now();
+        let path = request.path.as_str();
+        if path.starts_with("/time") {
+            return Reply::json(json!({ "serverTime": now.timestamp_millis() }).to_string());
+        }
+        if path.starts_with("/funding") {
+            return Reply::json(json!([{ "symbol": "BTCUSDT", "fundingTime": 1704067200000_i64, "fundingRate": "0.0001" }]).to_string());
+        }
+        let limit = query(path, "limit").unwrap_or(1000);
+        let opens = match query(path, "startTime") {
+            Some(start) => {
+                // the end time is the open time of the last kline.
+                let end = query(path, "endTime").unwrap_or(i64::MAX);
+                (start..=end).step_by(60_000).take(limit as usize).collect::<Vec<_>>()
+            }
+            // the latest klines, the last one still forming.
+            None => {
+                let current = now.duration_trunc(TimeDelta::minutes(1)).unwrap().timestamp_millis();
+                (0..limit).rev().map(|i| current - i * 60_000).collect()
+            }
+        };
+        Reply::json(Value::from(opens.into_iter().map(row).collect::<Vec<_>>()).to_string())
+    }
+
+    /// A Binance endpoint served by a mock server, its time and funding urls included.
+    struct Mocked<'m> {
+        binance: Binance<'m>,
+        time_url: String,
+        funding_url: String,
+    }
+
+    impl<'m> Mocked<'m> {
+        fn new(cmd: &'m Command, server: &MockServer) -> Self {
+            assert_eq!(cmd.base_url.as_deref(), Some(server.url.as_str()), "the command targets the mock server");
+            Self {
+                binance: Binance::build(cmd),
+                time_url: format!("{}/time", server.url),
+                funding_url: format!("{}/funding", server.url),
+            }
+        }
+    }
+
+    impl<'m> Endpoint<'m> for Mocked<'m> {
+        fn urls(&self) -> Vec<String> {
+            self.binance.urls()
+        }
+
+        fn time_url(&self) -> &str {
+            &self.time_url
+        }
+
+        fn server_time(&self, value: &Value) -> Result<DateTime<Utc>> {
+            self.binance.server_time(value)
+        }
+
+        fn rate_limit(&self) -> f64 {
+            self.binance.rate_limit()
+        }
+
+        fn limit(&self) -> i64 {
+            self.binance.limit()
+        }
+
+        fn row_size(&self) -> usize {
+            self.binance.row_size()
+        }
+
+        fn rows(&self, body: Value) -> Result<Vec<Value>> {
+            self.binance.rows(body)
+        }
+
+        fn kline(&self, value: &Value) -> Result<Box<dyn Kline>> {
+            self.binance.kline(value)
+        }
+
+        fn funding_urls(&self) -> Option<Vec<String>> {
+            Some(vec![self.funding_url.clone()])
+        }
+
+        fn symbols_url(&self) -> Option<String> {
+            self.binance.symbols_url()
+        }
+
+        fn symbols(&self, value: &Value) -> Result<Vec<String>> {
+            self.binance.symbols(value)
+        }
+    }
+
+    /// A Binance command targeting `server`, saving to `name.json` in a scratch directory.
+    fn command(server: &MockServer, name: &str) -> Command {
+        let mut cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        cmd.base_url = Some(server.url.clone());
+        cmd.output_file = Some(scratch_dir(name).join(format!("{name}.json")));
+        cmd.no_validate_symbol = true;
+        cmd
+    }
+
+    /// Runs `fetch_symbol` against the mock server, and reads the saved rows back.
+    async fn fetch_rows(cmd: &Command, server: &MockServer) -> Result<Vec<Value>> {
+        let market = Mocked::new(cmd, server);
+        fetch_symbol(cmd, &market, &client(cmd)?, &throttle(cmd), &Progress::Hidden).await?;
+        read_data_from_file(cmd.output_file.as_ref().unwrap())
+    }
+
+    fn open_times(rows: &[Value]) -> Vec<i64> {
+        rows.iter().map(|row| row[0].as_i64().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn compressed_responses_are_asked_for_and_decoded() {
+        let server = MockServer::start(|_| {
+            let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gzip.write_all(json!([row(1704067200000)]).to_string().as_bytes()).unwrap();
+            Reply::json(gzip.finish().unwrap()).with_header("Content-Encoding", "gzip")
+        })
+        .await;
+        let mut cmd = command(&server, "compression");
+        cmd.response_compression = true;
+
+        let market = Mocked::new(&cmd, &server);
+        let url = format!("{}/klines", server.url);
+        let rows = download_url(&client(&cmd).unwrap(), &market, &url, 1, &throttle(&cmd)).await.unwrap();
+        assert_eq!(rows, vec![row(1704067200000)]);
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/klines");
+        assert!(
+            requests[0].header("accept-encoding").is_some_and(|value| value.contains("gzip")),
+            "{requests:?}"
+        );
+    }
+
+    #[test]
+    fn audit_fails_on_the_gapped_file_only() {
+        let dir = scratch_dir("audit");
+        let minute = |i: i64| row(1704067200000 + i * 60_000);
+        write_to_file(&dir.join("clean.json"), &(0..10).map(minute).collect::<Vec<_>>()).unwrap();
+        write_to_file(&dir.join("gapped.json"), &[0, 1, 2, 5, 6, 7].map(minute)).unwrap();
+
+        let result = audit(&AuditCommand { input_dir: dir });
+        assert!(matches!(result, Err(Error::AuditFailed(1))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn funding_rates_are_saved_next_to_the_klines() {
+        let server = MockServer::start(exchange).await;
+        let mut cmd = command(&server, "funding");
+        cmd.with_funding = true;
+        cmd.last = Some(3);
+
+        fetch_rows(&cmd, &server).await.unwrap();
+        let funding = read_data_from_file::<Value>(&sibling_path(cmd.output_file.as_ref().unwrap(), "funding", "json")).unwrap();
+        assert_eq!(funding.len(), 1);
+        assert_eq!(funding[0]["fundingRate"], "0.0001");
+    }
+
+    #[tokio::test]
+    async fn forming_candles_are_dropped_with_only_closed() {
+        let server = MockServer::start(exchange).await;
+        let mut cmd = command(&server, "only-closed");
+        cmd.only_closed = true;
+        cmd.limit = Some(5);
+
+        let rows = fetch_rows(&cmd, &server).await.unwrap();
+        let current = Utc::now().duration_trunc(TimeDelta::minutes(1)).unwrap().timestamp_millis();
+        assert_eq!(rows.len(), 4);
+        assert!(open_times(&rows).iter().all(|&open_time| open_time < current));
+    }
+
+    #[tokio::test]
+    async fn last_returns_exactly_the_closed_candles_asked_for() {
+        let server = MockServer::start(exchange).await;
+        let mut cmd = command(&server, "last");
+        cmd.last = Some(3);
+
+        let rows = fetch_rows(&cmd, &server).await.unwrap();
+        let current = Utc::now().duration_trunc(TimeDelta::minutes(1)).unwrap().timestamp_millis();
+        assert_eq!(open_times(&rows), vec![current - 180_000, current - 120_000, current - 60_000]);
+        // one more candle is asked for, the forming one being dropped.
+        assert!(server.requests().iter().any(|request| request.path.contains("limit=4")));
+    }
+
+    #[tokio::test]
+    async fn deterministic_runs_save_identical_files() {
+        // the first chunks answer last, so only an ordered collection keeps the candles in order.
+        let server = MockServer::start(|request| {
+            let delay = 60 - query(&request.path, "startTime").map_or(0, |start| (start - 1704067200000) / 60_000);
+            exchange(request).with_delay(Duration::from_millis(delay as u64 * 5))
+        })
+        .await;
+        let mut files = Vec::new();
+        for name in ["deterministic-1", "deterministic-2"] {
+            let mut cmd = command(&server, name);
+            cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+            cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 10, 0).unwrap());
+            cmd.limit = Some(2);
+            cmd.deterministic = true;
+            fetch_rows(&cmd, &server).await.unwrap();
+            files.push(std::fs::read(cmd.output_file.unwrap()).unwrap());
+        }
+        assert_eq!(files[0], files[1]);
+    }
+
+    #[tokio::test]
+    async fn reverse_saves_the_newest_candle_first() {
+        let server = MockServer::start(exchange).await;
+        let mut cmd = command(&server, "reverse");
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 10, 0).unwrap());
+        cmd.limit = Some(3);
+        cmd.reverse = true;
+
+        let open_times = open_times(&fetch_rows(&cmd, &server).await.unwrap());
+        assert_eq!(open_times.len(), 10);
+        assert!(open_times.windows(2).all(|pair| pair[0] > pair[1]), "{open_times:?}");
+    }
+
+    #[test]
+    fn size_estimates_are_in_a_sensible_range() {
+        let mut cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap());
+        let market = endpoint(&cmd);
+        assert_eq!(market.chunks(), 87);
+        assert_eq!(estimate_candles(&cmd, market.as_ref(), market.chunks()), 86_400);
+
+        let saved = serde_json::to_string(&[row(1704067200000)]).unwrap().len();
+        let real = r#"[1704067200000,"42283.58000000","42298.62000000","42261.02000000","42298.61000000","35.92724000",1704067259999,"1519427.93171470",1327,"13.26617000","561066.07192360","0"],"#;
+        for size in [saved, real.len()] {
+            assert!(
+                (size / 2..size * 2).contains(&market.row_size()),
+                "{} for rows of {size} bytes",
+                market.row_size()
+            );
+        }
+    }
+
+    #[test]
+    fn plan_stats_count_the_chunks_and_candles_of_the_range() {
+        let mut cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        let market = endpoint(&cmd);
+        assert_eq!(market.chunks(), 2);
+        assert_eq!(estimate_candles(&cmd, market.as_ref(), market.chunks()), 1440);
+        drop(market);
+
+        // the latest candles fit in a single request.
+        cmd.from_date = None;
+        cmd.to_date = None;
+        cmd.last = Some(10);
+        let market = endpoint(&cmd);
+        assert_eq!(estimate_candles(&cmd, market.as_ref(), market.chunks()), 10);
+    }
+
+    #[test]
+    fn dry_run_lists_the_urls_and_stops_once_the_output_is_closed() {
+        let mut cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        cmd.dry_run = true;
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+
+        let mut listed = Vec::new();
+        plans(std::slice::from_ref(&cmd), &mut listed).unwrap();
+        let listed = String::from_utf8(listed).unwrap();
+        assert_eq!(listed.lines().filter(|line| line.starts_with("https://")).count(), 2, "{listed}");
+        assert!(
+            listed.ends_with("2 url(s) for BTCUSDT, ~0.1s of requests at most 40 per second.\n"),
+            "{listed}"
+        );
+
+        /// The stdout of `download-ticks fetch --dry-run | head -1`, once `head` exited.
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(plans(&[cmd.clone(), cmd], Closed).is_ok());
+    }
+
+    #[test]
+    fn truncate_drops_the_candles_outside_the_range() {
+        let cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        let market = endpoint(&cmd);
+        let from = Utc.with_ymd_and_hms(2024, 1, 1, 0, 1, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 1, 1, 0, 3, 0).unwrap();
+        let rows = (0..5).map(|i| row(1704067200000 + i * 60_000)).chain([json!("unparsable")]).collect();
+
+        let rows = truncate(rows, market.as_ref(), Some(from), Some(to));
+        assert_eq!(rows, vec![row(1704067260000), row(1704067320000), json!("unparsable")]);
+    }
+
+    #[test]
+    fn retime_converts_seconds_to_milliseconds() {
+        let dir = scratch_dir("retime");
+        let input_file = dir.join("gate.json");
+        let gate = json!(["1704067200", "100.5", "42300.1", "42310.0", "42290.0", "42295.2", "4250000.0", "true"]);
+        write_to_file(&input_file, &[gate]).unwrap();
+        let cmd = RetimeCommand {
+            input_file: input_file.clone(),
+            output_file: None,
+            to: TimeUnit::Milliseconds,
+            from: None,
+        };
+
+        retime(&cmd).unwrap();
+        let rows = read_data_from_file::<Vec<Value>>(&input_file).unwrap();
+        // the string timestamps stay strings.
+        assert_eq!(rows[0][0], "1704067200000");
+        assert_eq!(rows[0][1], "100.5");
+    }
+
+    #[tokio::test]
+    async fn binance_requests_are_spaced_out_by_its_rate_limit() {
+        let cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        let throttle = throttle(&cmd);
+        let started = Instant::now();
+        for _ in 0..11 {
+            throttle.wait().await;
+        }
+        // 10 spacings at 40 requests per second.
+        assert!(started.elapsed() >= Duration::from_millis(240), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn request_delay_prevails_over_a_faster_rate() {
+        let mut cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        cmd.request_delay = 100;
+        let throttle = throttle(&cmd);
+        let started = Instant::now();
+        for _ in 0..11 {
+            throttle.wait().await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(990), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_report_their_url() {
+        let server = MockServer::start(|_| Reply::json(r#"{"code":-1121,"msg":"Invalid symbol."}"#).with_status(400)).await;
+        let cmd = command(&server, "failed");
+        let market = Mocked::new(&cmd, &server);
+        let url = format!("{}/klines?symbol=BTCUSDT", server.url);
+
+        let error = download_url(&client(&cmd).unwrap(), &market, &url, 3, &throttle(&cmd)).await.unwrap_err();
+        assert!(error.to_string().contains(&url), "{error}");
+        assert!(error.to_string().contains("Invalid symbol."), "{error}");
+        // the error of the exchange isn't retried.
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_observed_on_429() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let server = MockServer::start(move |request| match counter.fetch_add(1, Ordering::Relaxed) {
+            0 => Reply::json("").with_status(429).with_header("Retry-After", "2"),
+            _ => exchange(request),
+        })
+        .await;
+        let cmd = command(&server, "retry-after");
+        let market = Mocked::new(&cmd, &server);
+        let url = format!("{}/klines?startTime=1704067200000&limit=1", server.url);
+
+        let started = Instant::now();
+        let rows = download_url(&client(&cmd).unwrap(), &market, &url, 2, &throttle(&cmd)).await.unwrap();
+        assert_eq!(rows, vec![row(1704067200000)]);
+        // rather than the 1s backoff of the first retry.
+        assert!(started.elapsed() >= Duration::from_secs(2), "{:?}", started.elapsed());
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn timed_out_requests_are_retried() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let server = MockServer::start(move |request| match counter.fetch_add(1, Ordering::Relaxed) {
+            0 => exchange(request).with_delay(Duration::from_secs(3)),
+            _ => exchange(request),
+        })
+        .await;
+        let mut cmd = command(&server, "timeout");
+        cmd.timeout = 1;
+        let market = Mocked::new(&cmd, &server);
+        let url = format!("{}/klines?startTime=1704067200000&limit=1", server.url);
+
+        let rows = download_url(&client(&cmd).unwrap(), &market, &url, 2, &throttle(&cmd)).await.unwrap();
+        assert_eq!(rows, vec![row(1704067200000)]);
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn the_library_klines_are_chronological_and_deduplicated() {
+        // every chunk, newest first, also holds the first kline of the next one.
+        let server = MockServer::start(|request| {
+            let (start, limit) = (query(&request.path, "startTime").unwrap(), query(&request.path, "limit").unwrap());
+            let rows = (0..=limit).rev().map(|i| row(start + i * 60_000)).collect::<Vec<_>>();
+            Reply::json(Value::from(rows).to_string())
+        })
+        .await;
+        let mut cmd = command(&server, "library");
+        cmd.limit = Some(2);
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap());
+        let market = Mocked::new(&cmd, &server);
+
+        let rows = klines(&cmd, &market, &client(&cmd).unwrap()).await.unwrap();
+        assert!(server.requests().len() > 1);
+        let start = 1704067200000;
+        assert_eq!(open_times(&rows), (0..5).map(|i| start + i * 60_000).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn merged_markets_keep_the_candles_closed_by_the_server_clock() {
+        // the exchange clock is 2 minutes behind, the candles of the last 2 minutes are still forming there.
+        let server = MockServer::start(|request| {
+            if request.path.starts_with("/time") {
+                let behind = Utc::now() - TimeDelta::minutes(2);
+                return Reply::json(json!({ "serverTime": behind.timestamp_millis() }).to_string());
+            }
+            exchange(request)
+        })
+        .await;
+        let mut cmd = command(&server, "merged-server-time");
+        cmd.markets = vec![Market::Binance, Market::Gate];
+        cmd.last = Some(3);
+        /// Every market served by the mock server, as Binance.
+        fn mocked(cmd: &Command) -> Box<dyn Endpoint<'_> + '_> {
+            let url = cmd.base_url.clone().unwrap();
+            Box::new(Mocked {
+                binance: Binance::build(cmd),
+                time_url: format!("{url}/time"),
+                funding_url: format!("{url}/funding"),
+            })
+        }
+
+        let current = Utc::now().duration_trunc(TimeDelta::minutes(1)).unwrap();
+        fetch_merged(&cmd, &client(&cmd).unwrap(), &Progress::Hidden, mocked).await.unwrap();
+        let klines = read_klines(cmd.output_file.as_ref().unwrap()).unwrap();
+        assert_eq!(klines.last().map(|kline| kline.open_time()), Some(current - TimeDelta::minutes(3)));
+        assert_eq!(server.requests().iter().filter(|request| request.path.starts_with("/time")).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_malformed_body_is_not_retried() {
+        let server = MockServer::start(|_| Reply::json("<html>502 Bad Gateway</html>")).await;
+        let cmd = command(&server, "malformed");
+        let market = Mocked::new(&cmd, &server);
+        let url = format!("{}/klines?startTime=1704067200000&limit=1", server.url);
+
+        let started = Instant::now();
+        let error = download_url(&client(&cmd).unwrap(), &market, &url, 3, &throttle(&cmd)).await.unwrap_err();
+        assert!(
+            matches!(&error, Error::Request { source, .. } if matches!(**source, Error::SerdeJson(_))),
+            "{error:?}"
+        );
+        assert_eq!(server.requests().len(), 1);
+        // without the 1s and 2s backoffs of the retries.
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn json_progress_reports_every_chunk() {
+        let server = MockServer::start(exchange).await;
+        let mut cmd = command(&server, "progress");
+        cmd.progress_format = ProgressFormat::Json;
+        let market = Mocked::new(&cmd, &server);
+        let urls = (0..3)
+            .map(|i| format!("{}/klines?startTime={}&limit=2", server.url, 1704067200000_i64 + i * 120_000))
+            .collect::<Vec<_>>();
+        let progress = Progress::build(&cmd, urls.len(), 6).unwrap();
+
+        let downloaded = download(
+            &client(&cmd).unwrap(),
+            &market,
+            &urls,
+            Concurrency::Ordered(2),
+            1,
+            &throttle(&cmd),
+            &progress,
+        )
+        .await;
+        assert_eq!((downloaded.succeeded, downloaded.rows.len()), (3, 6));
+        let Progress::Json { done, candles, .. } = &progress else {
+            panic!("a JSON progress is built");
+        };
+        assert_eq!((done.load(Ordering::Relaxed), candles.load(Ordering::Relaxed)), (3, 6));
+    }
+
+    #[tokio::test]
+    async fn clock_skew_is_measured_against_the_server_time() {
+        // the server clock is 5s late.
+        let server = MockServer::start(|_| Reply::json(json!({ "serverTime": Utc::now().timestamp_millis() - 5000 }).to_string())).await;
+        let cmd = command(&server, "doctor");
+        let market = Mocked::new(&cmd, &server);
+
+        let (latency, skew) = clock_skew(&client(&cmd).unwrap(), &market).await.unwrap();
+        assert!(latency < TimeDelta::seconds(1), "{latency}");
+        assert!((4_500..5_500).contains(&skew.num_milliseconds()), "{skew}");
+    }
+
+    #[test]
+    fn append_merges_the_new_rows_after_the_saved_ones() {
+        let path = scratch_dir("append").join("klines.json");
+        let open_time = |row: &Value| row[0].as_i64().and_then(DateTime::from_timestamp_millis);
+        let saved = [row(1704067200000), row(1704067260000)];
+        write_to_file(&path, &saved).unwrap();
+
+        // the boundary candle downloaded again replaces the saved one.
+        let mut boundary = row(1704067260000);
+        boundary[4] = json!("1.75");
+        let merged = append(&path, vec![row(1704067320000), boundary.clone()], open_time).unwrap();
+        assert_eq!(merged, vec![row(1704067200000), boundary, row(1704067320000)]);
+
+        write_to_file(&path, &[json!({ "time": 1 })]).unwrap();
+        assert!(matches!(
+            append(&path, vec![row(1704067320000)], open_time),
+            Err(Error::IncompatibleFile(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unlisted_symbols_are_rejected_with_the_closest_one() {
+        let server = MockServer::start(|_| Reply::json(json!({ "symbols": [{ "symbol": "BTCUSDT" }, { "symbol": "ETHUSDT" }] }).to_string())).await;
+        let cmd = command(&server, "symbols");
+        let (market, client) = (Mocked::new(&cmd, &server), client(&cmd).unwrap());
+
+        check_symbols(&client, &market, &["btcusdt".to_string(), "ETHUSDT".to_string()])
+            .await
+            .unwrap();
+        let error = check_symbols(&client, &market, &["BTCUSDX".to_string()]).await.unwrap_err();
+        assert!(
+            matches!(&error, Error::InvalidSymbol(message) if message.contains("did you mean BTCUSDT?")),
+            "{error}"
+        );
+        assert_eq!(server.requests()[0].path, "/exchangeInfo");
+    }
+
+    #[test]
+    fn convert_writes_each_format_from_a_json_file() {
+        let dir = scratch_dir("convert");
+        let input_file = dir.join("klines.json");
+        write_to_file(&input_file, &[row(1704067200000), row(1704067260000)]).unwrap();
+        let convert_to = |format: OutputFormat, extension: &str| {
+            let cmd = ConvertCommand {
+                input_file: input_file.clone(),
+                output_file: dir.join(format!("klines.{extension}")),
+                format,
+                symbol: Some("BTCUSDT".to_string()),
+                interval: None,
+            };
+            convert(&cmd).map(|_| cmd.output_file)
+        };
+
+        let csv = std::fs::read_to_string(convert_to(OutputFormat::Csv, "csv").unwrap()).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(
+            csv.starts_with("open_time,open,high,low,close,volume,close_time,vwap,net_taker_volume\n2024-01-01T00:00:00.000Z,1,2,0.5,1.5,10,"),
+            "{csv}"
+        );
+        let ndjson = read_data_from_file::<Value>(&convert_to(OutputFormat::Ndjson, "ndjson").unwrap()).unwrap();
+        assert_eq!(ndjson, vec![row(1704067200000), row(1704067260000)]);
+        // the interval of the sqlite rows is inferred from the spacing.
+        let database = rusqlite::Connection::open(convert_to(OutputFormat::Sqlite, "sqlite").unwrap()).unwrap();
+        let (count, interval) = database
+            .query_row("SELECT COUNT(*), MAX(interval) FROM klines WHERE symbol = 'BTCUSDT'", [], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, interval.as_str()), (2, "1m"));
+
+        write_to_file(&input_file, &[json!({ "time": 1 })]).unwrap();
+        assert!(matches!(convert_to(OutputFormat::Csv, "csv"), Err(Error::InvalidFile)));
+    }
+
+    #[test]
+    fn info_skips_the_unreadable_files_of_a_directory() {
+        let dir = scratch_dir("info");
+        write_to_file(&dir.join("a.json"), &[row(1704067200000), row(1704067260000)]).unwrap();
+        write_to_file(&dir.join("b.json"), &[json!({ "time": 1 })]).unwrap();
+        let info_of = |input_files: Vec<PathBuf>| info(&InfoCommand { input_files });
+
+        assert!(info_of(vec![dir.clone()]).is_ok());
+        assert!(info_of(vec![dir.join("a.json"), dir.join("b.json")]).is_ok());
+        // a single file, or only unreadable ones, fail.
+        assert!(matches!(info_of(vec![dir.join("b.json")]), Err(Error::InvalidFile)));
+        assert!(matches!(info_of(vec![dir.join("b.json"), dir.join("b.json")]), Err(Error::InvalidFile)));
+    }
+
+    #[test]
+    fn the_summary_reports_the_prices_and_the_volume() {
+        let cmd = Command::new(Market::Binance, "BTCUSDT", Interval::M1);
+        let market = endpoint(&cmd);
+        let mut rows = [row(1704067200000), row(1704067260000), row(1704067320000)];
+        rows[1][2] = json!("2.5");
+        rows[2][4] = json!("1.25");
+        let klines = rows.iter().map(|row| market.kline(row)).collect::<Result<Vec<_>>>().unwrap();
+
+        assert!(matches!(summary::<Box<dyn Kline>>(&[]), Err(Error::MissingData)));
+        let summary = summary(&klines).unwrap();
+        assert!(summary.contains("Number of elements: 3\n"), "{summary}");
+        assert!(summary.contains("Interval: 1m\n"), "{summary}");
+        assert!(summary.contains("Gaps: 0\n"), "{summary}");
+        assert!(summary.contains("Price: low 0.5, high 2.5, last close 1.25\n"), "{summary}");
+        assert!(summary.contains("Volume: 30\n"), "{summary}");
+    }
+}
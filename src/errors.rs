@@ -9,9 +9,85 @@ pub enum Error {
     #[error("Unable to read you input file. Make sure it is json kline valid data.")]
     InvalidFile,
 
+    #[error("Unable to infer the interval of the klines, pass it with --interval.")]
+    UnknownInterval,
+
+    #[error("Can't resample klines spaced by {0}s to {1}, the interval must be a multiple of their spacing.")]
+    InvalidResample(i64, crate::cli::Interval),
+
     #[error("Invalid given datetime.")]
     InvalidDatetime,
 
+    #[error("The range holds ~{0} candles, use a coarser interval or raise --max-candles.")]
+    TooManyCandles(u64),
+
+    #[error("The download needs {0} requests (more than --max-chunks), narrow the range, use a coarser interval or pass --force.")]
+    TooManyChunks(usize),
+
+    #[error("Conflicting klines at {0}: {1}")]
+    ConflictingDuplicate(chrono::DateTime<chrono::Utc>, String),
+
+    #[error("Several symbols need an existing directory as --output-file.")]
+    MultipleSymbols,
+
+    #[error("Invalid symbol: {0}")]
+    InvalidSymbol(String),
+
+    #[error("Unexpected kline row: {0}")]
+    InvalidRow(String),
+
+    #[error("The directory {} doesn't exist, create it or pass --mkdirs.", .0.display())]
+    MissingDirectory(std::path::PathBuf),
+
+    #[error("Can't append to a {0} file, only json files are supported.")]
+    UnsupportedAppend(crate::cli::OutputFormat),
+
+    #[error("Can't print {0} klines to stdout, only json and ndjson are supported, or set --output-stdout-format.")]
+    UnsupportedStdout(crate::cli::OutputFormat),
+
+    #[error("The output file already reaches {0}, nothing to resume.")]
+    NothingToResume(chrono::DateTime<chrono::Utc>),
+
+    #[error("The file {} holds other klines, it can't be appended to.", .0.display())]
+    IncompatibleFile(std::path::PathBuf),
+
+    #[error("{0} doesn't serve {1} klines, pick another interval.")]
+    UnsupportedInterval(crate::cli::Market, crate::cli::Interval),
+
+    #[error("{0} returns at most {1} closed candles with --last, use a date range instead.")]
+    TooManyLast(crate::cli::Market, u64),
+
+    #[error("{0} only serves the candles since {1}, pick a later --from-date.")]
+    UnservedRange(crate::cli::Market, chrono::DateTime<chrono::Utc>),
+
+    #[error("The Binance futures market has no {0} endpoint.")]
+    NoFuturesEndpoint(crate::cli::BinanceEndpoint),
+
+    #[error("The market doesn't provide funding rates.")]
+    NoFunding,
+
+    #[error("{0} url(s) failed, pass --allow-partial to save the downloaded klines anyway.")]
+    PartialDownload(usize),
+
+    #[error("Audit failed for {0} file(s).")]
+    AuditFailed(usize),
+
+    #[error("{0} exchange(s) unreachable.")]
+    Unreachable(usize),
+
+    #[error("The exchange returned an error: {0}")]
+    Exchange(String),
+
+    #[error("The exchange answered {status}")]
+    Http {
+        status: reqwest::StatusCode,
+        /// How long the exchange asks to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("Request to {url} failed: {source}")]
+    Request { url: String, source: Box<Error> },
+
     #[error("{0}")]
     Io(#[from] std::io::Error),
 
@@ -21,12 +97,53 @@ pub enum Error {
     #[error("{0}")]
     SerdeJson(#[from] serde_json::Error),
 
+    #[error("{0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    #[error("{0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error("{0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("{0}")]
+    Sqlite(#[from] rusqlite::Error),
+
     #[error("{0}")]
     Indicatif(#[from] indicatif::style::TemplateError),
 
+    #[error("{0}")]
+    Join(#[from] tokio::task::JoinError),
+
     #[error("{0}")]
     Str(#[from] std::str::Utf8Error),
 
     #[error("{0}")]
     Parse(#[from] std::num::ParseIntError),
 }
+
+impl Error {
+    /// Whether the request may succeed if sent again (timeouts, connection errors, 429 and 5xx statuses).
+    ///
+    /// Decoding errors are permanent: the same response would fail the same way,
+    /// unlike the I/O errors met while the body is still being read.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Reqwest(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            // the body is streamed into the parser, so a timeout or reset while reading it surfaces as a JSON error.
+            Error::SerdeJson(e) => e.is_io(),
+            Error::Http { .. } => true,
+            Error::Request { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// How long the exchange asks to wait before retrying, if it said so.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            Error::Http { retry_after, .. } => *retry_after,
+            Error::Request { source, .. } => source.retry_after(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/// Simple moving average over `period` values.
+///
+/// # Returns
+/// One value per input, `None` until `period` values are available.
+pub fn sma(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut sum = 0.0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            sum += value;
+            if i >= period {
+                sum -= values[i - period];
+            }
+            (i + 1 >= period).then(|| sum / period as f64)
+        })
+        .collect()
+}
+
+/// Exponential moving average over `period` values, seeded with the first simple moving average.
+///
+/// # Returns
+/// One value per input, `None` until `period` values are available.
+pub fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut previous: Option<f64> = None;
+    sma(values, period)
+        .into_iter()
+        .zip(values)
+        .map(|(seed, value)| {
+            previous = match previous {
+                Some(previous) => Some(alpha * value + (1.0 - alpha) * previous),
+                None => seed,
+            };
+            previous
+        })
+        .collect()
+}
+
+/// Relative strength index over `period` changes, using Wilder's smoothing.
+///
+/// # Returns
+/// One value per input, `None` until `period` changes are available.
+pub fn rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if values.len() <= period {
+        return result;
+    }
+
+    let changes = values.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>();
+    let (mut gain, mut loss) = changes[..period]
+        .iter()
+        .fold((0.0, 0.0), |(gain, loss), change| (gain + change.max(0.0), loss + (-change).max(0.0)));
+    gain /= period as f64;
+    loss /= period as f64;
+
+    let index = |gain: f64, loss: f64| if loss == 0.0 { 100.0 } else { 100.0 - 100.0 / (1.0 + gain / loss) };
+    result[period] = Some(index(gain, loss));
+    for (i, change) in changes.iter().enumerate().skip(period) {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        result[i + 1] = Some(index(gain, loss));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            match (actual, expected) {
+                (Some(actual), Some(expected)) => assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}"),
+                _ => assert_eq!(actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn sma_20_averages_the_last_20_closes() {
+        let closes = (1..=25).map(f64::from).collect::<Vec<_>>();
+        let sma = sma(&closes, 20);
+        assert!(sma[..19].iter().all(Option::is_none));
+        // (1 + ... + 20) / 20, then each next window drops the oldest close.
+        assert_close(&sma[19..], &[Some(10.5), Some(11.5), Some(12.5), Some(13.5), Some(14.5), Some(15.5)]);
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_sma() {
+        // alpha = 2 / (3 + 1), the seed being (1 + 2 + 3) / 3.
+        assert_close(&ema(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), &[None, None, Some(2.0), Some(3.0), Some(4.0)]);
+    }
+
+    #[test]
+    fn rsi_uses_wilder_smoothing() {
+        // average gain and loss of 0.5 over the first 2 changes, then (0.5 + 1) / 2 and 0.5 / 2.
+        assert_close(&rsi(&[1.0, 2.0, 1.0, 2.0], 2), &[None, None, Some(50.0), Some(75.0)]);
+        assert_close(&rsi(&[1.0, 2.0, 3.0], 2), &[None, None, Some(100.0)]);
+        assert_close(&rsi(&[1.0, 2.0], 2), &[None, None]);
+    }
+}
@@ -0,0 +1,41 @@
+//! A command-line tool to fetch and save candlestick (kline) data from exchanges.
+//!
+//! This tool allows you to download historical candlestick data from exchanges
+//! (e.g., Binance), split the time range into chunks (to respect API limits),
+//! and save the results to a JSON file.
+//!
+//! ## Features
+//! - Supports multiple exchanges.
+//! - Handles large time ranges by splitting them into smaller intervals.
+//! - Saves data in the original exchange format or a custom JSON structure.
+//!
+//! ## Usage
+//! The tool is designed to be flexible and easy to use. See the `cli` module for command-line options.
+//!
+//! The downloader can also be embedded, see [`fetch_klines`]:
+//!
+//! ```no_run
+//! use download_ticks::{Interval, Market, fetch_klines};
+//!
+//! # async fn example() -> download_ticks::Result<()> {
+//! let klines = fetch_klines(Market::Binance, "BTCUSDT", Interval::H1, None, None).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod checks;
+mod cli;
+mod commands;
+mod errors;
+mod indicators;
+mod market;
+mod progress;
+mod resample;
+#[cfg(test)]
+mod testing;
+mod utils;
+
+pub use cli::{Cli, Command, Commands, Interval, Market};
+pub use commands::{fetch_klines, run};
+pub use errors::{Error, Result};
+pub use market::{Candle, Endpoint, Kline, endpoint};
@@ -0,0 +1,425 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Deserializer, de::Error as DeError};
+use serde_json::Value;
+
+use super::{Kline, close_time};
+use crate::{
+    cli::TimeUnit,
+    errors::{Error, Result},
+};
+
+/// Column indexes of an array-based kline, so new exchanges can be described by data alone.
+#[derive(Debug, Clone)]
+pub struct ArrayLayout {
+    /// Number of columns of a row, so the rows of another exchange aren't read through the layout.
+    pub columns: usize,
+    pub open_time: usize,
+    /// Exchanges only returning the open time have no close time column.
+    pub close_time: Option<usize>,
+    pub open: usize,
+    pub high: usize,
+    pub low: usize,
+    pub close: usize,
+    pub volume: usize,
+    pub quote_volume: Option<usize>,
+    pub trades: Option<usize>,
+    pub taker_buy_volume: Option<usize>,
+    /// A `true`/`false` column telling whether the kline is closed.
+    pub closed: Option<usize>,
+    pub time_unit: TimeUnit,
+}
+
+impl ArrayLayout {
+    /// `[open_time, open, high, low, close, volume, close_time, quote_volume, trades, taker_buy_volume, ...]` in milliseconds.
+    pub const BINANCE: ArrayLayout = ArrayLayout {
+        columns: 12,
+        open_time: 0,
+        close_time: Some(6),
+        open: 1,
+        high: 2,
+        low: 3,
+        close: 4,
+        volume: 5,
+        quote_volume: Some(7),
+        trades: Some(8),
+        taker_buy_volume: Some(9),
+        closed: None,
+        time_unit: TimeUnit::Milliseconds,
+    };
+
+    /// `[time, quote_volume, close, high, low, open, base_volume, window_closed]` in seconds.
+    pub const GATE: ArrayLayout = ArrayLayout {
+        columns: 8,
+        open_time: 0,
+        close_time: None,
+        open: 5,
+        high: 3,
+        low: 4,
+        close: 2,
+        volume: 6,
+        quote_volume: Some(1),
+        trades: None,
+        taker_buy_volume: None,
+        closed: Some(7),
+        time_unit: TimeUnit::Seconds,
+    };
+
+    /// `[time, open, high, low, close, vwap, volume, count]` in seconds.
+    pub const KRAKEN: ArrayLayout = ArrayLayout {
+        columns: 8,
+        open_time: 0,
+        close_time: None,
+        open: 1,
+        high: 2,
+        low: 3,
+        close: 4,
+        volume: 6,
+        quote_volume: None,
+        trades: Some(7),
+        taker_buy_volume: None,
+        closed: None,
+        time_unit: TimeUnit::Seconds,
+    };
+
+    /// `[time, low, high, open, close, volume]` in seconds.
+    pub const COINBASE: ArrayLayout = ArrayLayout {
+        columns: 6,
+        open_time: 0,
+        close_time: None,
+        open: 3,
+        high: 2,
+        low: 1,
+        close: 4,
+        volume: 5,
+        quote_volume: None,
+        trades: None,
+        taker_buy_volume: None,
+        closed: None,
+        time_unit: TimeUnit::Seconds,
+    };
+
+    /// `[start_time, open, high, low, close, volume, turnover]` in milliseconds.
+    pub const BYBIT: ArrayLayout = ArrayLayout {
+        columns: 7,
+        open_time: 0,
+        close_time: None,
+        open: 1,
+        high: 2,
+        low: 3,
+        close: 4,
+        volume: 5,
+        quote_volume: Some(6),
+        trades: None,
+        taker_buy_volume: None,
+        closed: None,
+        time_unit: TimeUnit::Milliseconds,
+    };
+
+    /// `[ts, open, high, low, close, volume, volume_currency, volume_currency_quote, confirm]` in milliseconds.
+    pub const OKX: ArrayLayout = ArrayLayout {
+        columns: 9,
+        open_time: 0,
+        close_time: None,
+        open: 1,
+        high: 2,
+        low: 3,
+        close: 4,
+        volume: 5,
+        quote_volume: Some(7),
+        trades: None,
+        taker_buy_volume: None,
+        closed: None,
+        time_unit: TimeUnit::Milliseconds,
+    };
+
+    /// Guesses the layout of a row from its number of columns.
+    ///
+    /// Gate and Kraken rows both have 8 columns, but only Gate sends the time as a string.
+    pub fn detect(row: &[Value]) -> Option<&'static ArrayLayout> {
+        match row.len() {
+            12 => Some(&Self::BINANCE),
+            9 => Some(&Self::OKX),
+            8 if row[0].is_number() => Some(&Self::KRAKEN),
+            8 => Some(&Self::GATE),
+            7 => Some(&Self::BYBIT),
+            6 => Some(&Self::COINBASE),
+            _ => None,
+        }
+    }
+
+    /// Indexes of the timestamp columns.
+    pub fn timestamp_columns(&self) -> Vec<usize> {
+        std::iter::once(self.open_time).chain(self.close_time).collect()
+    }
+
+    /// Parses the timestamp at `index`, given as a number or a string.
+    pub fn timestamp(&self, row: &[Value], index: usize) -> Result<DateTime<Utc>> {
+        from_epoch(integer(row, index)?, self.time_unit)
+    }
+}
+
+/// Represents a single candlestick (kline) read through an `ArrayLayout`.
+#[derive(Debug)]
+pub struct ArrayKline {
+    open_time: DateTime<Utc>,
+    close_time: DateTime<Utc>,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    quote_volume: Option<f64>,
+    trades: Option<u64>,
+    taker_buy_volume: Option<f64>,
+    closed: Option<bool>,
+}
+
+impl ArrayKline {
+    /// Reads a kline from a JSON array following `layout`.
+    ///
+    /// Without a close time column, the kline closes one `interval` after its open time.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidRow` if the value isn't an array of `layout.columns` or a column is missing or not valid.
+    pub fn from_value(value: &Value, layout: &ArrayLayout, interval: Option<Duration>) -> Result<Self> {
+        let row = value
+            .as_array()
+            .filter(|row| row.len() == layout.columns)
+            .ok_or_else(|| Error::InvalidRow(value.to_string()))?;
+        let open_time = layout.timestamp(row, layout.open_time)?;
+        let close_time = match layout.close_time {
+            Some(index) => layout.timestamp(row, index)?,
+            None => close_time(open_time, interval),
+        };
+
+        Ok(Self {
+            open_time,
+            close_time,
+            open: float(row, layout.open)?,
+            high: float(row, layout.high)?,
+            low: float(row, layout.low)?,
+            close: float(row, layout.close)?,
+            volume: float(row, layout.volume)?,
+            quote_volume: layout.quote_volume.map(|index| float(row, index)).transpose()?,
+            trades: layout.trades.map(|index| integer(row, index)).transpose()?.map(|trades| trades as u64),
+            taker_buy_volume: layout.taker_buy_volume.map(|index| float(row, index)).transpose()?,
+            closed: layout.closed.map(|index| flag(row, index)).transpose()?,
+        })
+    }
+}
+
+impl Kline for ArrayKline {
+    fn open_time(&self) -> DateTime<Utc> {
+        self.open_time
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        self.close_time
+    }
+
+    fn open(&self) -> f64 {
+        self.open
+    }
+
+    fn high(&self) -> f64 {
+        self.high
+    }
+
+    fn low(&self) -> f64 {
+        self.low
+    }
+
+    fn close(&self) -> f64 {
+        self.close
+    }
+
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    fn is_closed(&self, now: DateTime<Utc>) -> bool {
+        self.closed.unwrap_or(self.close_time < now)
+    }
+
+    fn vwap(&self) -> Option<f64> {
+        self.quote_volume
+            .filter(|_| self.volume > 0.0)
+            .map(|quote_volume| quote_volume / self.volume)
+    }
+
+    fn net_taker_volume(&self) -> Option<f64> {
+        // taker buy - taker sell = taker buy - (volume - taker buy)
+        self.taker_buy_volume.map(|taker_buy_volume| 2.0 * taker_buy_volume - self.volume)
+    }
+
+    fn trades(&self) -> Option<u64> {
+        self.trades
+    }
+}
+
+/// Converts an epoch timestamp given in `unit`.
+fn from_epoch(timestamp: i64, unit: TimeUnit) -> Result<DateTime<Utc>> {
+    match unit {
+        TimeUnit::Seconds => DateTime::<Utc>::from_timestamp_secs(timestamp),
+        TimeUnit::Milliseconds => DateTime::<Utc>::from_timestamp_millis(timestamp),
+    }
+    .ok_or(Error::InvalidDatetime)
+}
+
+/// Deserializes a string of milliseconds into a `DateTime<Utc>`, as Bybit and OKX send their times.
+///
+/// # Errors
+/// Returns an error if the string cannot be parsed as an integer or if the timestamp is invalid.
+pub(super) fn to_datetime_millis<'de, D>(deserializer: D) -> std::result::Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp = String::deserialize(deserializer)?.parse().map_err(DeError::custom)?;
+    from_epoch(timestamp, TimeUnit::Milliseconds).map_err(DeError::custom)
+}
+
+/// Reads the column at `index` as a float, given as a number or a string.
+fn float(row: &[Value], index: usize) -> Result<f64> {
+    match row.get(index) {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| Error::InvalidRow(Value::from(row).to_string()))
+}
+
+/// Reads the column at `index` as a boolean, given as a boolean or a `"true"`/`"false"` string.
+fn flag(row: &[Value], index: usize) -> Result<bool> {
+    match row.get(index) {
+        Some(Value::Bool(b)) => Some(*b),
+        Some(Value::String(s)) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| Error::InvalidRow(Value::from(row).to_string()))
+}
+
+/// Reads the column at `index` as an integer, given as a number or a string.
+pub fn integer(row: &[Value], index: usize) -> Result<i64> {
+    match row.get(index) {
+        Some(Value::Number(n)) => n.as_i64(),
+        Some(Value::String(s)) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| Error::InvalidRow(Value::from(row).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    fn row(value: Value) -> Vec<Value> {
+        value.as_array().cloned().unwrap()
+    }
+
+    #[test]
+    fn binance_rows_map_both_timestamps_in_milliseconds() {
+        let row = row(json!([
+            1704067200000_i64,
+            "1",
+            "2",
+            "0.5",
+            "1.5",
+            "10",
+            1704070799999_i64,
+            "15",
+            3,
+            "4",
+            "6",
+            "0"
+        ]));
+        let layout = ArrayLayout::detect(&row).unwrap();
+        assert_eq!(layout.timestamp_columns(), vec![0, 6]);
+        assert_eq!(
+            layout.timestamp(&row, layout.open_time).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+        );
+        let close_time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 59, 59).unwrap() + chrono::Duration::milliseconds(999);
+        assert_eq!(layout.timestamp(&row, 6).unwrap(), close_time);
+    }
+
+    #[test]
+    fn gate_rows_map_a_string_open_time_in_seconds() {
+        let row = row(json!([
+            "1704067200",
+            "6342383.5",
+            "42475.6",
+            "42557.6",
+            "42269.1",
+            "42283.5",
+            "149.57",
+            "true"
+        ]));
+        let layout = ArrayLayout::detect(&row).unwrap();
+        assert_eq!(layout.timestamp_columns(), vec![0]);
+        assert_eq!(
+            layout.timestamp(&row, layout.open_time).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn rows_of_the_same_length_are_told_apart_by_their_time_type() {
+        let kraken = row(json!([1704067200, "1", "2", "0.5", "1.5", "1.2", "10", 3]));
+        assert_eq!(ArrayLayout::detect(&kraken).map(|layout| layout.time_unit), Some(TimeUnit::Seconds));
+        assert!(ArrayLayout::detect(&kraken).unwrap().close_time.is_none());
+        assert!(ArrayLayout::detect(&row(json!([1, 2, 3]))).is_none());
+    }
+
+    #[test]
+    fn invalid_columns_are_reported() {
+        let row = row(json!(["soon", "1"]));
+        assert!(matches!(integer(&row, 0), Err(Error::InvalidRow(_))));
+        assert!(matches!(integer(&row, 5), Err(Error::InvalidRow(_))));
+    }
+
+    #[test]
+    fn the_binance_mapping_reads_the_prices_and_the_taker_volume() {
+        let value = json!([1704067200000_i64, "1", "2", "0.5", "1.5", "10", 1704070799999_i64, "15", 3, "4", "6", "0"]);
+        let kline = ArrayKline::from_value(&value, &ArrayLayout::BINANCE, None).unwrap();
+        assert_eq!(kline.close_time().timestamp_millis(), 1704070799999);
+        assert_eq!(
+            (kline.open(), kline.high(), kline.low(), kline.close(), kline.volume()),
+            (1.0, 2.0, 0.5, 1.5, 10.0)
+        );
+        assert_eq!(kline.vwap(), Some(1.5));
+        assert_eq!(kline.net_taker_volume(), Some(-2.0));
+        assert_eq!(kline.trades(), Some(3));
+        assert!(kline.is_closed(Utc::now()));
+    }
+
+    #[test]
+    fn the_gate_mapping_reads_its_reordered_prices_and_closed_column() {
+        let value = json!(["1704067200", "15", "1.5", "2", "0.5", "1", "10", "false"]);
+        let kline = ArrayKline::from_value(&value, &ArrayLayout::GATE, Some(Duration::hours(1))).unwrap();
+        assert_eq!(kline.close_time().timestamp_millis(), 1704070799999);
+        assert_eq!(
+            (kline.open(), kline.high(), kline.low(), kline.close(), kline.volume()),
+            (1.0, 2.0, 0.5, 1.5, 10.0)
+        );
+        assert_eq!(kline.vwap(), Some(1.5));
+        assert_eq!((kline.net_taker_volume(), kline.trades()), (None, None));
+        assert!(!kline.is_closed(Utc::now()));
+    }
+
+    #[test]
+    fn a_row_of_another_length_is_not_read_through_a_mapping() {
+        let kraken = json!([1704067200, "1", "2", "0.5", "1.5", "1.2", "10", 3]);
+        assert!(matches!(
+            ArrayKline::from_value(&kraken, &ArrayLayout::BINANCE, None),
+            Err(Error::InvalidRow(_))
+        ));
+        // same length as Gate, but the trade count isn't a closed flag.
+        assert!(matches!(
+            ArrayKline::from_value(&kraken, &ArrayLayout::GATE, None),
+            Err(Error::InvalidRow(_))
+        ));
+    }
+}
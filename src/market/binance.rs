@@ -1,32 +1,106 @@
-#![allow(unused)]
+use chrono::{DateTime, Timelike, Utc};
+use reqwest::header::HeaderMap;
+use serde_json::Value;
 
-use chrono::{DateTime, Utc, serde::ts_milliseconds};
-use serde::Deserialize;
-use serde_this_or_that::{as_f64, as_u64};
-
-use super::{Endpoint, Kline};
-use crate::{cli::Command, utils::split_intervals};
+use super::{ArrayKline, ArrayLayout, Endpoint, Kline};
+use crate::{
+    cli::{BinanceMarket, Command, Interval},
+    errors::{Error, Result},
+    utils::split_intervals,
+};
 
 /// A wrapper for the Binance exchange configuration.
 pub struct Binance<'b>(&'b Command);
 
 impl<'b> Binance<'b> {
-    const BASE_URL: &'b str = "https://api.binance.com/api/v3/klines";
+    const BASE_URL: &'b str = "https://api.binance.com/api/v3";
+    const FUTURES_URL: &'b str = "https://fapi.binance.com/fapi/v1";
+    const FUNDING_URL: &'b str = "https://fapi.binance.com/fapi/v1/fundingRate";
+    const TIME_URL: &'b str = "https://api.binance.com/api/v3/time";
+    const FUTURES_TIME_URL: &'b str = "https://fapi.binance.com/fapi/v1/time";
+    const MAX_LIMIT: i64 = 1000;
+    /// Request weight allowed per minute.
+    const WEIGHT_LIMIT: u64 = 6000;
 
     pub fn build(command: &'b Command) -> Self {
         Self(command)
     }
+
+    /// The `--base-url` override, or the built-in url of the `--binance-market`.
+    fn base_url(&self) -> &str {
+        let default = match self.0.binance_market {
+            BinanceMarket::Spot => Self::BASE_URL,
+            BinanceMarket::Futures => Self::FUTURES_URL,
+        };
+        self.0.base_url.as_deref().unwrap_or(default)
+    }
 }
 
 impl<'b> Endpoint<'b> for Binance<'b> {
+    fn time_url(&self) -> &str {
+        match self.0.binance_market {
+            BinanceMarket::Spot => Self::TIME_URL,
+            BinanceMarket::Futures => Self::FUTURES_TIME_URL,
+        }
+    }
+
+    fn server_time(&self, value: &Value) -> Result<DateTime<Utc>> {
+        value["serverTime"]
+            .as_i64()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or(Error::InvalidDatetime)
+    }
+
+    fn rate_limit(&self) -> f64 {
+        // 6000 request weight per minute, a klines request weighs 2.
+        40.0
+    }
+
+    fn limit(&self) -> i64 {
+        self.0.candles_per_request(Self::MAX_LIMIT)
+    }
+
+    fn throttle_from_headers(&self, headers: &HeaderMap) -> Option<std::time::Duration> {
+        let used = headers.get("x-mbx-used-weight-1m")?.to_str().ok()?.parse::<u64>().ok()?;
+        // past 80% of the weight, wait for the minute window to reset.
+        if used * 5 < Self::WEIGHT_LIMIT * 4 {
+            return None;
+        }
+        let now = Utc::now();
+        let elapsed = std::time::Duration::new(now.second() as u64, now.nanosecond() % 1_000_000_000);
+        Some(std::time::Duration::from_secs(60).saturating_sub(elapsed))
+    }
+
+    fn row_size(&self) -> usize {
+        170
+    }
+
+    fn rows(&self, body: Value) -> Result<Vec<Value>> {
+        match body {
+            Value::Array(rows) => Ok(rows),
+            // e.g., `{"code":-1121,"msg":"Invalid symbol."}`
+            other => Err(Error::Exchange(format!(
+                "{} ({})",
+                other["msg"].as_str().unwrap_or("unknown error"),
+                other["code"]
+            ))),
+        }
+    }
+
+    fn kline(&self, value: &Value) -> Result<Box<dyn Kline>> {
+        Ok(Box::new(ArrayKline::from_value(value, &ArrayLayout::BINANCE, None)?))
+    }
+
     fn urls(&self) -> Vec<String> {
-        let symbol = &self.0.symbol;
+        let symbol = self.0.symbol.to_uppercase();
         let interval = &self.0.interval;
-        let burl = Self::BASE_URL;
-        let mut url = format!("{burl}?symbol={symbol}&interval={interval}&limit=1000");
+        let burl = format!("{}/{}", self.base_url(), self.0.binance_endpoint);
+        // one more candle for the one still forming.
+        let limit = self.0.last.map_or(self.limit() as u64, |n| n + 1);
+        let mut url = format!("{burl}?symbol={symbol}&interval={interval}&limit={limit}");
 
         if let (Some(start), Some(end)) = (self.0.from_date, self.0.to_date) {
-            let datetimes = split_intervals(start, end, interval);
+            let datetimes = split_intervals(start, end, interval, self.limit());
             let urls = datetimes
                 .iter()
                 .map(|(start, end)| {
@@ -45,43 +119,142 @@ impl<'b> Endpoint<'b> for Binance<'b> {
         }
         vec![url]
     }
-}
 
-/// Represents a single candlestick (kline) from binance.
-#[derive(Debug, Deserialize)]
-pub struct BinanceKline {
-    #[serde(rename = "0", with = "ts_milliseconds")]
-    open_time: DateTime<Utc>,
-    #[serde(rename = "1", deserialize_with = "as_f64")]
-    open_price: f64,
-    #[serde(rename = "2", deserialize_with = "as_f64")]
-    high_price: f64,
-    #[serde(rename = "3", deserialize_with = "as_f64")]
-    low_price: f64,
-    #[serde(rename = "4", deserialize_with = "as_f64")]
-    close_price: f64,
-    #[serde(rename = "5", deserialize_with = "as_f64")]
-    volume: f64,
-    #[serde(rename = "6", with = "ts_milliseconds")]
-    close_time: DateTime<Utc>,
-    #[serde(rename = "7", deserialize_with = "as_f64")]
-    quote_asset_volume: f64,
-    #[serde(rename = "8")]
-    number_of_trades: u64,
-    #[serde(rename = "9", deserialize_with = "as_f64")]
-    taker_buy_base_volume: f64,
-    #[serde(rename = "10", deserialize_with = "as_f64")]
-    taker_buy_quote_volume: f64,
-    #[serde(rename = "11", deserialize_with = "as_u64")]
-    ignore: u64,
+    fn symbols_url(&self) -> Option<String> {
+        Some(format!("{}/exchangeInfo", self.base_url()))
+    }
+
+    fn symbols(&self, value: &Value) -> Result<Vec<String>> {
+        let symbols = value["symbols"].as_array().ok_or_else(|| Error::Exchange(value.to_string()))?;
+        Ok(symbols.iter().filter_map(|symbol| symbol["symbol"].as_str().map(String::from)).collect())
+    }
+
+    fn funding_urls(&self) -> Option<Vec<String>> {
+        let symbol = self.0.symbol.to_uppercase();
+        let burl = Self::FUNDING_URL;
+        let mut url = format!("{burl}?symbol={symbol}&limit=1000");
+
+        // funding happens every 8 hours, so chunking as H8 klines keeps each request under the limit.
+        if let (Some(start), Some(end)) = (self.0.from_date, self.0.to_date) {
+            let datetimes = split_intervals(start, end, &Interval::H8, 1000);
+            let urls = datetimes
+                .iter()
+                .map(|(start, end)| {
+                    let _start = start.timestamp_millis();
+                    let _end = end.timestamp_millis();
+                    format!("{url}&startTime={_start}&endTime={_end}")
+                })
+                .collect::<Vec<_>>();
+            return Some(urls);
+        }
+
+        if let (Some(start), None) = (self.0.from_date, self.0.to_date) {
+            url = format!("{url}&startTime={}", start.timestamp_millis());
+        } else if let (None, Some(end)) = (self.0.from_date, self.0.to_date) {
+            url = format!("{url}&endTime={}", end.timestamp_millis());
+        }
+        Some(vec![url])
+    }
 }
 
-impl Kline for BinanceKline {
-    fn open_time(&self) -> DateTime<Utc> {
-        self.open_time
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::{BinanceEndpoint, Market};
+
+    /// The 2024-01-01 00:00 BTCUSDT 1h kline.
+    fn row() -> Value {
+        json!([
+            1704067200000_i64,
+            "42283.58000000",
+            "42554.57000000",
+            "42261.02000000",
+            "42475.23000000",
+            "1271.68108000",
+            1704070799999_i64,
+            "53957248.99010610",
+            47134,
+            "682.57581000",
+            "28957416.81500840",
+            "0"
+        ])
+    }
+
+    fn command() -> Command {
+        Command::new(Market::Binance, "BTCUSDT", Interval::H1)
+    }
+
+    #[test]
+    fn vwap_is_the_quote_volume_over_the_volume() {
+        let cmd = command();
+        let kline = Binance::build(&cmd).kline(&row()).unwrap();
+        // 53957248.99010610 / 1271.68108
+        let vwap = kline.vwap().unwrap();
+        assert!((vwap - 42429.86).abs() < 0.01, "{vwap}");
+        assert_eq!(kline.trades(), Some(47134));
+    }
+
+    #[test]
+    fn net_taker_volume_is_the_taker_buys_minus_the_taker_sells() {
+        let cmd = command();
+        let kline = Binance::build(&cmd).kline(&row()).unwrap();
+        // 682.57581 - (1271.68108 - 682.57581)
+        assert!((kline.net_taker_volume().unwrap() - 93.47054).abs() < 1e-6);
+    }
+
+    #[test]
+    fn symbols_are_uppercased_in_the_urls() {
+        let mut cmd = command();
+        cmd.symbol = "btcusdt".to_string();
+        let urls = Binance::build(&cmd).urls();
+        assert_eq!(urls, vec!["https://api.binance.com/api/v3/klines?symbol=BTCUSDT&interval=1h&limit=1000"]);
+    }
+
+    #[test]
+    fn ui_klines_are_requested_from_their_own_path() {
+        let mut cmd = command();
+        cmd.binance_endpoint = BinanceEndpoint::UiKlines;
+        let urls = Binance::build(&cmd).urls();
+        assert!(
+            urls[0].starts_with("https://api.binance.com/api/v3/uiKlines?symbol=BTCUSDT&"),
+            "{}",
+            urls[0]
+        );
+    }
+
+    #[test]
+    fn ranges_are_split_into_chunks_of_the_limit() {
+        let mut cmd = command();
+        cmd.from_date = Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        cmd.to_date = Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap());
+        // 1440 hours, 1000 per request.
+        let urls = Binance::build(&cmd).urls();
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].ends_with("&startTime=1704067200000&endTime=1707663600000"), "{}", urls[0]);
+    }
+
+    #[test]
+    fn errors_are_read_from_the_body() {
+        let cmd = command();
+        let error = Binance::build(&cmd).rows(json!({"code": -1121, "msg": "Invalid symbol."})).unwrap_err();
+        assert_eq!(error.to_string(), Error::Exchange("Invalid symbol. (-1121)".to_string()).to_string());
     }
 
-    fn close_time(&self) -> DateTime<Utc> {
-        self.close_time
+    #[test]
+    fn the_used_weight_pauses_near_the_limit() {
+        let cmd = command();
+        let market = Binance::build(&cmd);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", "1000".parse().unwrap());
+        assert!(market.throttle_from_headers(&headers).is_none());
+        headers.insert("x-mbx-used-weight-1m", "5900".parse().unwrap());
+        assert!(
+            market
+                .throttle_from_headers(&headers)
+                .is_some_and(|pause| pause <= std::time::Duration::from_secs(60))
+        );
     }
 }
@@ -0,0 +1,159 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc, serde::ts_seconds};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_this_or_that::as_f64;
+
+use super::{Endpoint, Kline, close_time};
+use crate::{
+    cli::{Command, Interval},
+    errors::{Error, Result},
+    utils::split_intervals,
+};
+
+/// A wrapper for the Coinbase exchange configuration.
+pub struct Coinbase<'b>(&'b Command);
+
+impl<'b> Coinbase<'b> {
+    const BASE_URL: &'b str = "https://api.exchange.coinbase.com/products";
+    const TIME_URL: &'b str = "https://api.exchange.coinbase.com/time";
+    const MAX_LIMIT: i64 = 300;
+
+    pub fn build(command: &'b Command) -> Self {
+        Self(command)
+    }
+
+    /// The `--base-url` override, or the built-in klines url.
+    fn base_url(&self) -> &str {
+        self.0.base_url.as_deref().unwrap_or(Self::BASE_URL)
+    }
+}
+
+impl<'b> Endpoint<'b> for Coinbase<'b> {
+    fn time_url(&self) -> &str {
+        Self::TIME_URL
+    }
+
+    fn server_time(&self, value: &Value) -> Result<DateTime<Utc>> {
+        value["epoch"]
+            .as_f64()
+            .and_then(|epoch| DateTime::from_timestamp_millis((epoch * 1000.0) as i64))
+            .ok_or(Error::InvalidDatetime)
+    }
+
+    fn rate_limit(&self) -> f64 {
+        // 10 requests per second per IP on public endpoints.
+        8.0
+    }
+
+    fn limit(&self) -> i64 {
+        self.0.candles_per_request(Self::MAX_LIMIT)
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        // the 60, 300, 900, 3600, 21600 and 86400 seconds granularities.
+        matches!(
+            interval,
+            Interval::M1 | Interval::M5 | Interval::M15 | Interval::H1 | Interval::H6 | Interval::D1
+        )
+    }
+
+    /// The latest page, the one still forming excluded.
+    fn max_last(&self) -> Option<u64> {
+        Some(Self::MAX_LIMIT as u64 - 1)
+    }
+
+    fn row_size(&self) -> usize {
+        60
+    }
+
+    fn rows(&self, body: Value) -> Result<Vec<Value>> {
+        match body {
+            // newest first, reversed to keep every market chronological.
+            Value::Array(mut rows) => {
+                rows.reverse();
+                Ok(rows)
+            }
+            // e.g., `{"message":"NotFound"}`
+            other => Err(Error::Exchange(other["message"].as_str().unwrap_or("unknown error").to_string())),
+        }
+    }
+
+    fn kline(&self, value: &Value) -> Result<Box<dyn Kline>> {
+        let mut kline = CoinbaseKline::deserialize(value)?;
+        kline.interval = Some(self.0.interval.duration());
+        Ok(Box::new(kline))
+    }
+
+    fn urls(&self) -> Vec<String> {
+        let symbol = self.0.symbol.to_uppercase();
+        let interval = &self.0.interval;
+        // Coinbase expects the granularity in seconds.
+        let granularity = interval.duration().num_seconds();
+        let url = format!("{}/{symbol}/candles?granularity={granularity}", self.base_url());
+        let iso = |datetime: DateTime<Utc>| datetime.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        // Coinbase needs both ends of the range, a single date is completed with a full chunk.
+        let span = interval.duration() * (self.limit() - 1) as i32;
+        let (start, end) = match (self.0.from_date, self.0.to_date) {
+            (Some(start), Some(end)) => (start, end),
+            (Some(start), None) => (start, start + span),
+            (None, Some(end)) => (end - span, end),
+            (None, None) => return vec![url],
+        };
+
+        split_intervals(start, end, interval, self.limit())
+            .iter()
+            .map(|(start, end)| format!("{url}&start={}&end={}", iso(*start), iso(*end)))
+            .collect()
+    }
+}
+
+/// Represents a single candlestick (kline) from Coinbase.
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseKline {
+    #[serde(rename = "0", with = "ts_seconds")]
+    time: DateTime<Utc>,
+    #[serde(rename = "1", deserialize_with = "as_f64")]
+    low_price: f64,
+    #[serde(rename = "2", deserialize_with = "as_f64")]
+    high_price: f64,
+    #[serde(rename = "3", deserialize_with = "as_f64")]
+    open_price: f64,
+    #[serde(rename = "4", deserialize_with = "as_f64")]
+    close_price: f64,
+    #[serde(rename = "5", deserialize_with = "as_f64")]
+    volume: f64,
+    /// The requested granularity, Coinbase only returning the open time.
+    #[serde(skip)]
+    interval: Option<Duration>,
+}
+
+impl Kline for CoinbaseKline {
+    fn open_time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        close_time(self.time, self.interval)
+    }
+
+    fn open(&self) -> f64 {
+        self.open_price
+    }
+
+    fn high(&self) -> f64 {
+        self.high_price
+    }
+
+    fn low(&self) -> f64 {
+        self.low_price
+    }
+
+    fn close(&self) -> f64 {
+        self.close_price
+    }
+
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+}
@@ -1,32 +1,100 @@
-#![allow(unused)]
+use chrono::{DateTime, Utc};
+use serde_json::Value;
 
-use chrono::{DateTime, Utc, serde::ts_seconds};
-use serde::{Deserialize, de::Error as DeError};
-use serde_this_or_that::{as_bool, as_f64};
-
-use super::{Endpoint, Kline};
-use crate::{cli::Command, errors::Error, utils::split_intervals};
+use super::{ArrayKline, ArrayLayout, Endpoint, Kline};
+use crate::{
+    cli::{Command, Interval},
+    errors::{self, Error},
+    utils::split_intervals,
+};
 
 /// A wrapper for the Gate.io exchange configuration.
 pub struct Gate<'b>(&'b Command);
 
 impl<'b> Gate<'b> {
     const BASE_URL: &'b str = "https://api.gateio.ws/api/v4/spot/candlesticks";
+    const FUNDING_URL: &'b str = "https://api.gateio.ws/api/v4/futures/usdt/funding_rate";
+    const TIME_URL: &'b str = "https://api.gateio.ws/api/v4/spot/time";
+    const PAIRS_URL: &'b str = "https://api.gateio.ws/api/v4/spot/currency_pairs";
+    const MAX_LIMIT: i64 = 1000;
 
     pub fn build(command: &'b Command) -> Self {
         Self(command)
     }
+
+    /// The `--base-url` override, or the built-in klines url.
+    fn base_url(&self) -> &str {
+        self.0.base_url.as_deref().unwrap_or(Self::BASE_URL)
+    }
+
+    /// Gate names the week `7d`, the other intervals like Binance.
+    fn interval(interval: &Interval) -> String {
+        match interval {
+            Interval::W1 => "7d".to_string(),
+            other => other.to_string(),
+        }
+    }
 }
 
 impl<'b> Endpoint<'b> for Gate<'b> {
+    fn time_url(&self) -> &str {
+        Self::TIME_URL
+    }
+
+    fn server_time(&self, value: &Value) -> errors::Result<DateTime<Utc>> {
+        value["server_time"]
+            .as_i64()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or(Error::InvalidDatetime)
+    }
+
+    fn rate_limit(&self) -> f64 {
+        // 200 requests per 10 seconds per public endpoint.
+        15.0
+    }
+
+    fn limit(&self) -> i64 {
+        self.0.candles_per_request(Self::MAX_LIMIT)
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        // its `30d` isn't a calendar month.
+        matches!(
+            interval,
+            Interval::M1 | Interval::M5 | Interval::M15 | Interval::M30 | Interval::H1 | Interval::H4 | Interval::H8 | Interval::D1 | Interval::W1
+        )
+    }
+
+    fn row_size(&self) -> usize {
+        90
+    }
+
+    fn rows(&self, body: Value) -> errors::Result<Vec<Value>> {
+        match body {
+            // an out of range request is a legitimate empty array.
+            Value::Array(rows) => Ok(rows),
+            // e.g., `{"label":"INVALID_CURRENCY_PAIR","message":"Invalid currency pair BTCUSDT"}`
+            other => Err(Error::Exchange(format!(
+                "{} ({})",
+                other["message"].as_str().unwrap_or("unknown error"),
+                other["label"].as_str().unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn kline(&self, value: &Value) -> errors::Result<Box<dyn Kline>> {
+        let interval = self.0.interval.duration();
+        Ok(Box::new(ArrayKline::from_value(value, &ArrayLayout::GATE, Some(interval))?))
+    }
+
     fn urls(&self) -> Vec<String> {
         let symbol = &self.0.symbol;
-        let interval = &self.0.interval;
-        let burl = Self::BASE_URL;
+        let interval = Self::interval(&self.0.interval);
+        let burl = self.base_url();
         let mut url = format!("{burl}?currency_pair={symbol}&interval={interval}");
 
         if let (Some(start), Some(end)) = (self.0.from_date, self.0.to_date) {
-            let datetimes = split_intervals(start, end, interval);
+            let datetimes = split_intervals(start, end, &self.0.interval, self.limit());
             let urls = datetimes
                 .iter()
                 .map(|(start, end)| {
@@ -43,54 +111,130 @@ impl<'b> Endpoint<'b> for Gate<'b> {
         } else if let (None, Some(end)) = (self.0.from_date, self.0.to_date) {
             url = format!("{url}&to={}", end.timestamp());
         } else {
-            url = format!("{url}&limit=1000");
+            // one more candle for the one still forming.
+            let limit = self.0.last.map_or(self.limit() as u64, |n| n + 1);
+            url = format!("{url}&limit={limit}");
         }
         vec![url]
     }
-}
 
-/// Represents a single candlestick (kline) from Gate.
-#[derive(Debug, Deserialize)]
-pub struct GateKline {
-    #[serde(rename = "0", deserialize_with = "to_datetime")]
-    time: DateTime<Utc>,
-    #[serde(rename = "1", deserialize_with = "as_f64")]
-    quote_volume: f64,
-    #[serde(rename = "2", deserialize_with = "as_f64")]
-    close_price: f64,
-    #[serde(rename = "3", deserialize_with = "as_f64")]
-    high_price: f64,
-    #[serde(rename = "4", deserialize_with = "as_f64")]
-    low_price: f64,
-    #[serde(rename = "5", deserialize_with = "as_f64")]
-    open_price: f64,
-    #[serde(rename = "6", deserialize_with = "as_f64")]
-    base_volume: f64,
-    #[serde(rename = "6", deserialize_with = "as_bool")]
-    window: bool,
-}
+    fn symbols_url(&self) -> Option<String> {
+        // the pairs are listed next to the candlesticks, a `--base-url` laid out otherwise isn't checked.
+        match &self.0.base_url {
+            Some(url) => url.strip_suffix("/candlesticks").map(|spot| format!("{spot}/currency_pairs")),
+            None => Some(Self::PAIRS_URL.to_string()),
+        }
+    }
 
-impl Kline for GateKline {
-    fn open_time(&self) -> DateTime<Utc> {
-        self.time
+    fn symbols(&self, value: &Value) -> errors::Result<Vec<String>> {
+        let pairs = value.as_array().ok_or_else(|| Error::Exchange(value.to_string()))?;
+        Ok(pairs.iter().filter_map(|pair| pair["id"].as_str().map(String::from)).collect())
     }
 
-    fn close_time(&self) -> DateTime<Utc> {
-        self.time
+    fn funding_urls(&self) -> Option<Vec<String>> {
+        let symbol = &self.0.symbol;
+        let burl = Self::FUNDING_URL;
+        let mut url = format!("{burl}?contract={symbol}&limit=1000");
+
+        // funding happens every 8 hours, so chunking as H8 klines keeps each request under the limit.
+        if let (Some(start), Some(end)) = (self.0.from_date, self.0.to_date) {
+            let datetimes = split_intervals(start, end, &Interval::H8, 1000);
+            let urls = datetimes
+                .iter()
+                .map(|(start, end)| {
+                    let _start = start.timestamp();
+                    let _end = end.timestamp();
+                    format!("{url}&from={_start}&to={_end}")
+                })
+                .collect::<Vec<_>>();
+            return Some(urls);
+        }
+
+        if let (Some(start), None) = (self.0.from_date, self.0.to_date) {
+            url = format!("{url}&from={}", start.timestamp());
+        } else if let (None, Some(end)) = (self.0.from_date, self.0.to_date) {
+            url = format!("{url}&to={}", end.timestamp());
+        }
+        Some(vec![url])
     }
 }
 
-/// Deserializes a string into a `DateTime<Utc>`.
-///
-/// This function supports timestamps in seconds.
-///
-/// # Errors
-/// Returns an error if the string cannot be parsed as an integer or if the timestamp is invalid.
-pub fn to_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let deserialize = String::deserialize(deserializer)?;
-    let timestamp = deserialize.parse::<i64>().map_err(DeError::custom)?;
-    DateTime::<Utc>::from_timestamp_secs(timestamp).ok_or_else(|| DeError::custom(Error::InvalidDatetime))
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::Market;
+
+    fn command() -> Command {
+        Command::new(Market::Gate, "BTC_USDT", Interval::H1)
+    }
+
+    #[test]
+    fn a_real_row_is_read_through_its_closed_window_column() {
+        let cmd = command();
+        let market = Gate::build(&cmd);
+        // `[time, quote_volume, close, high, low, open, base_volume, window_closed]`
+        let row = json!([
+            "1704067200",
+            "6342383.50367",
+            "42475.6",
+            "42557.6",
+            "42269.1",
+            "42283.5",
+            "149.5744",
+            "true"
+        ]);
+        let kline = market.kline(&row).unwrap();
+        assert_eq!(kline.open_time(), Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(kline.close_time().timestamp_millis(), 1704070799999);
+        assert_eq!(
+            (kline.open(), kline.high(), kline.low(), kline.close()),
+            (42283.5, 42557.6, 42269.1, 42475.6)
+        );
+        assert_eq!(kline.volume(), 149.5744);
+        assert!((kline.vwap().unwrap() - 42402.9).abs() < 0.1);
+        assert!(kline.is_closed(Utc::now()));
+
+        let forming = json!(["1704067200", "0", "1", "1", "1", "1", "0", "false"]);
+        let kline = market.kline(&forming).unwrap();
+        assert!(!kline.is_closed(Utc::now()));
+        assert_eq!(kline.vwap(), None);
+    }
+
+    #[test]
+    fn the_week_is_asked_in_days() {
+        let cmd = Command::new(Market::Gate, "BTC_USDT", Interval::W1);
+        assert!(Gate::build(&cmd).urls()[0].contains("&interval=7d"));
+    }
+
+    #[test]
+    fn an_empty_array_is_no_rows() {
+        let cmd = command();
+        assert!(Gate::build(&cmd).rows(json!([])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn an_error_object_is_an_exchange_error() {
+        let cmd = command();
+        let body = json!({"label": "INVALID_CURRENCY_PAIR", "message": "Invalid currency pair BTCUSDT"});
+        match Gate::build(&cmd).rows(body) {
+            Err(Error::Exchange(message)) => assert_eq!(message, "Invalid currency pair BTCUSDT (INVALID_CURRENCY_PAIR)"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn the_pairs_are_listed_next_to_the_base_url() {
+        let mut cmd = command();
+        assert_eq!(Gate::build(&cmd).symbols_url().as_deref(), Some(Gate::PAIRS_URL));
+        cmd.base_url = Some("http://127.0.0.1:8080/api/v4/spot/candlesticks".to_string());
+        assert_eq!(
+            Gate::build(&cmd).symbols_url().as_deref(),
+            Some("http://127.0.0.1:8080/api/v4/spot/currency_pairs")
+        );
+        cmd.base_url = Some("http://127.0.0.1:8080/klines".to_string());
+        assert_eq!(Gate::build(&cmd).symbols_url(), None);
+    }
 }
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Duration, Utc, serde::ts_seconds};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_this_or_that::as_f64;
+
+use super::{Endpoint, Kline, close_time};
+use crate::{
+    cli::{Command, Interval},
+    errors::{Error, Result},
+};
+
+/// A wrapper for the Kraken exchange configuration.
+pub struct Kraken<'b>(&'b Command);
+
+impl<'b> Kraken<'b> {
+    const BASE_URL: &'b str = "https://api.kraken.com/0/public/OHLC";
+    const TIME_URL: &'b str = "https://api.kraken.com/0/public/Time";
+    const MAX_LIMIT: i64 = 720;
+
+    pub fn build(command: &'b Command) -> Self {
+        Self(command)
+    }
+
+    /// The `--base-url` override, or the built-in klines url.
+    fn base_url(&self) -> &str {
+        self.0.base_url.as_deref().unwrap_or(Self::BASE_URL)
+    }
+}
+
+impl<'b> Endpoint<'b> for Kraken<'b> {
+    fn time_url(&self) -> &str {
+        Self::TIME_URL
+    }
+
+    fn server_time(&self, value: &Value) -> Result<DateTime<Utc>> {
+        value["result"]["unixtime"]
+            .as_i64()
+            .and_then(DateTime::from_timestamp_secs)
+            .ok_or(Error::InvalidDatetime)
+    }
+
+    fn rate_limit(&self) -> f64 {
+        // public endpoints allow about one request per second.
+        1.0
+    }
+
+    fn limit(&self) -> i64 {
+        self.0.candles_per_request(Self::MAX_LIMIT)
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        // 1, 5, 15, 30, 60, 240, 1440, 10080 and 21600 minutes, the last one matching no interval.
+        matches!(
+            interval,
+            Interval::M1 | Interval::M5 | Interval::M15 | Interval::M30 | Interval::H1 | Interval::H4 | Interval::D1 | Interval::W1
+        )
+    }
+
+    /// The latest page, the one still forming excluded.
+    fn max_last(&self) -> Option<u64> {
+        Some(Self::MAX_LIMIT as u64 - 1)
+    }
+
+    /// The latest page, whatever the `since` of the request.
+    fn oldest(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        now.checked_sub_signed(self.0.interval.duration() * (Self::MAX_LIMIT - 1) as i32)
+    }
+
+    fn row_size(&self) -> usize {
+        80
+    }
+
+    fn rows(&self, body: Value) -> Result<Vec<Value>> {
+        // e.g., `{"error":["EQuery:Unknown asset pair"]}`
+        if let Some(errors) = body["error"].as_array()
+            && !errors.is_empty()
+        {
+            let messages = errors.iter().filter_map(Value::as_str).collect::<Vec<_>>();
+            return Err(Error::Exchange(messages.join(", ")));
+        }
+
+        // the rows are nested under the pair name, next to the `last` cursor,
+        // e.g., `{"error":[],"result":{"XXBTZUSD":[[...]],"last":1688671200}}`
+        match body {
+            Value::Object(mut object) => match object.remove("result") {
+                Some(Value::Object(result)) => result
+                    .into_iter()
+                    .find_map(|(key, value)| match value {
+                        Value::Array(rows) if key != "last" => Some(rows),
+                        _ => None,
+                    })
+                    .ok_or_else(|| Error::Exchange("no klines in the response".to_string())),
+                other => Err(Error::Exchange(format!("{other:?}"))),
+            },
+            other => Err(Error::Exchange(other.to_string())),
+        }
+    }
+
+    fn kline(&self, value: &Value) -> Result<Box<dyn Kline>> {
+        let mut kline = KrakenKline::deserialize(value)?;
+        kline.interval = Some(self.0.interval.duration());
+        Ok(Box::new(kline))
+    }
+
+    fn urls(&self) -> Vec<String> {
+        let pair = self.0.symbol.to_uppercase();
+        // Kraken expects the interval in minutes.
+        let interval = self.0.interval.duration().num_minutes();
+        let burl = self.base_url();
+        let mut url = format!("{burl}?pair={pair}&interval={interval}");
+
+        // Kraken has no end parameter and only keeps its latest candles, so a single request serves the whole range,
+        // the candles past its end are truncated afterwards.
+        if let Some(start) = self.0.from_date {
+            url = format!("{url}&since={}", start.timestamp());
+        }
+        vec![url]
+    }
+}
+
+/// Represents a single candlestick (kline) from Kraken.
+#[derive(Debug, Deserialize)]
+pub struct KrakenKline {
+    #[serde(rename = "0", with = "ts_seconds")]
+    time: DateTime<Utc>,
+    #[serde(rename = "1", deserialize_with = "as_f64")]
+    open_price: f64,
+    #[serde(rename = "2", deserialize_with = "as_f64")]
+    high_price: f64,
+    #[serde(rename = "3", deserialize_with = "as_f64")]
+    low_price: f64,
+    #[serde(rename = "4", deserialize_with = "as_f64")]
+    close_price: f64,
+    #[serde(rename = "5", deserialize_with = "as_f64")]
+    vwap: f64,
+    #[serde(rename = "6", deserialize_with = "as_f64")]
+    volume: f64,
+    #[serde(rename = "7")]
+    count: u64,
+    /// The requested interval, Kraken only returning the open time.
+    #[serde(skip)]
+    interval: Option<Duration>,
+}
+
+impl Kline for KrakenKline {
+    fn open_time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        close_time(self.time, self.interval)
+    }
+
+    fn open(&self) -> f64 {
+        self.open_price
+    }
+
+    fn high(&self) -> f64 {
+        self.high_price
+    }
+
+    fn low(&self) -> f64 {
+        self.low_price
+    }
+
+    fn close(&self) -> f64 {
+        self.close_price
+    }
+
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    fn vwap(&self) -> Option<f64> {
+        (self.volume > 0.0).then_some(self.vwap)
+    }
+
+    fn trades(&self) -> Option<u64> {
+        Some(self.count)
+    }
+}
@@ -1,18 +1,449 @@
+mod array;
 mod binance;
+mod bybit;
+mod coinbase;
 mod gate;
+mod kraken;
+mod okx;
 
+pub use array::*;
 pub use binance::*;
+pub use bybit::*;
+pub use coinbase::*;
 pub use gate::*;
+pub use kraken::*;
+pub use okx::*;
 
-use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cli::{Command, Interval, Market};
+use crate::errors::{Error, Result};
+use crate::utils::read_data_from_file;
 
 /// Trait for kline data.
 pub trait Kline {
     fn open_time(&self) -> DateTime<Utc>;
     fn close_time(&self) -> DateTime<Utc>;
+    fn open(&self) -> f64;
+    fn high(&self) -> f64;
+    fn low(&self) -> f64;
+    fn close(&self) -> f64;
+    fn volume(&self) -> f64;
+
+    /// Whether the kline was closed at `now`, i.e. it's not forming anymore.
+    fn is_closed(&self, now: DateTime<Utc>) -> bool {
+        self.close_time() < now
+    }
+
+    /// Volume weighted average price, if the market provides the quote volume.
+    fn vwap(&self) -> Option<f64> {
+        None
+    }
+
+    /// Taker buy volume minus taker sell volume, if the market provides the taker volumes.
+    fn net_taker_volume(&self) -> Option<f64> {
+        None
+    }
+
+    /// Number of trades, if the market provides it.
+    fn trades(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<K: Kline + ?Sized> Kline for Box<K> {
+    fn open_time(&self) -> DateTime<Utc> {
+        (**self).open_time()
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        (**self).close_time()
+    }
+
+    fn open(&self) -> f64 {
+        (**self).open()
+    }
+
+    fn high(&self) -> f64 {
+        (**self).high()
+    }
+
+    fn low(&self) -> f64 {
+        (**self).low()
+    }
+
+    fn close(&self) -> f64 {
+        (**self).close()
+    }
+
+    fn volume(&self) -> f64 {
+        (**self).volume()
+    }
+
+    fn is_closed(&self, now: DateTime<Utc>) -> bool {
+        (**self).is_closed(now)
+    }
+
+    fn vwap(&self) -> Option<f64> {
+        (**self).vwap()
+    }
+
+    fn net_taker_volume(&self) -> Option<f64> {
+        (**self).net_taker_volume()
+    }
+
+    fn trades(&self) -> Option<u64> {
+        (**self).trades()
+    }
+}
+
+/// Exchange-agnostic representation of a kline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Candle {
+    open_time: DateTime<Utc>,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    close_time: DateTime<Utc>,
+    is_closed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vwap: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    net_taker_volume: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trades: Option<u64>,
+}
+
+impl Candle {
+    /// Average size in bytes of a candle, once saved as JSON.
+    pub const ROW_SIZE: usize = 180;
+    /// Average size in bytes of a candle in a compressed Parquet file.
+    pub const PARQUET_ROW_SIZE: usize = 55;
+    /// Average size in bytes of a candle in a SQLite database, its key index included.
+    pub const SQLITE_ROW_SIZE: usize = 120;
+
+    pub fn new<T: Kline + ?Sized>(kline: &T, now: DateTime<Utc>) -> Self {
+        Self {
+            open_time: kline.open_time(),
+            open: kline.open(),
+            high: kline.high(),
+            low: kline.low(),
+            close: kline.close(),
+            volume: kline.volume(),
+            close_time: kline.close_time(),
+            is_closed: kline.is_closed(now),
+            vwap: None,
+            net_taker_volume: None,
+            trades: None,
+        }
+    }
+
+    /// Aggregates consecutive klines into a single candle spanning `open_time` to `close_time`.
+    ///
+    /// The number of trades is summed only when every kline provides it.
+    ///
+    /// # Returns
+    /// `None` if there are no klines.
+    pub fn aggregate<T: Kline>(klines: &[T], open_time: DateTime<Utc>, close_time: DateTime<Utc>, now: DateTime<Utc>) -> Option<Self> {
+        let (first, last) = (klines.first()?, klines.last()?);
+        Some(Self {
+            open_time,
+            open: first.open(),
+            high: klines.iter().map(|kline| kline.high()).fold(f64::NEG_INFINITY, f64::max),
+            low: klines.iter().map(|kline| kline.low()).fold(f64::INFINITY, f64::min),
+            close: last.close(),
+            volume: klines.iter().map(|kline| kline.volume()).sum(),
+            close_time,
+            is_closed: close_time < now,
+            vwap: None,
+            net_taker_volume: None,
+            trades: klines.iter().map(|kline| kline.trades()).sum(),
+        })
+    }
+
+    /// Adds the volume weighted average price, when the kline provides it.
+    pub fn with_vwap<T: Kline + ?Sized>(mut self, kline: &T) -> Self {
+        self.vwap = kline.vwap();
+        self
+    }
+
+    /// Adds the net taker volume, when the kline provides it.
+    pub fn with_net_taker<T: Kline + ?Sized>(mut self, kline: &T) -> Self {
+        self.net_taker_volume = kline.net_taker_volume();
+        self
+    }
+}
+
+impl Kline for Candle {
+    fn open_time(&self) -> DateTime<Utc> {
+        self.open_time
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        self.close_time
+    }
+
+    fn open(&self) -> f64 {
+        self.open
+    }
+
+    fn high(&self) -> f64 {
+        self.high
+    }
+
+    fn low(&self) -> f64 {
+        self.low
+    }
+
+    fn close(&self) -> f64 {
+        self.close
+    }
+
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    fn is_closed(&self, _now: DateTime<Utc>) -> bool {
+        self.is_closed
+    }
+
+    fn vwap(&self) -> Option<f64> {
+        self.vwap
+    }
+
+    fn net_taker_volume(&self) -> Option<f64> {
+        self.net_taker_volume
+    }
+
+    fn trades(&self) -> Option<u64> {
+        self.trades
+    }
 }
 
 /// Trait to compute urls.
 pub trait Endpoint<'m> {
     fn urls(&self) -> Vec<String>;
+
+    /// Url returning the market server time.
+    fn time_url(&self) -> &str;
+
+    /// Reads the server time from the `time_url` response.
+    fn server_time(&self, value: &Value) -> Result<DateTime<Utc>>;
+
+    /// Default maximum number of requests per second, safely under the market limits.
+    fn rate_limit(&self) -> f64;
+
+    /// Number of candles asked per request, the market maximum unless lowered by `--limit`.
+    fn limit(&self) -> i64;
+
+    /// Whether the market serves klines at `interval`.
+    fn supports(&self, _interval: &Interval) -> bool {
+        true
+    }
+
+    /// Most candles `--last` can fetch, when the market answers it with a single page of its latest candles.
+    fn max_last(&self) -> Option<u64> {
+        None
+    }
+
+    /// Oldest open time served at `now`, for the markets only keeping their latest candles.
+    fn oldest(&self, _now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Average size in bytes of a row returned by the market, once saved as JSON.
+    fn row_size(&self) -> usize;
+
+    /// Extracts the rows of a response body.
+    ///
+    /// # Errors
+    /// Returns `Error::Exchange` if the market answered with an error instead of rows.
+    fn rows(&self, body: Value) -> Result<Vec<Value>> {
+        match body {
+            Value::Array(rows) => Ok(rows),
+            other => Err(Error::Exchange(other.to_string())),
+        }
+    }
+
+    /// Parses a row returned by the market into its kline type.
+    fn kline(&self, value: &Value) -> Result<Box<dyn Kline>>;
+
+    /// Number of requests the download takes, `urls` only holding the first page of a cursor-paginated market.
+    fn chunks(&self) -> usize {
+        self.urls().len()
+    }
+
+    /// Url of the page preceding `page`, for markets paginated with a cursor rather than time windows.
+    ///
+    /// Such markets only return the most recent page from `urls`, and walk backward in time from there.
+    ///
+    /// # Arguments
+    /// * `page` - The rows of the page just downloaded.
+    /// * `collected` - The number of rows downloaded so far.
+    fn next_url(&self, _page: &[Value], _collected: usize) -> Option<String> {
+        None
+    }
+
+    /// Urls of the perpetual futures funding rate history, if the market provides it.
+    fn funding_urls(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Pause to observe before the next request, read from the headers of a response.
+    ///
+    /// Lets the markets publishing their quota usage slow down before answering with 429s.
+    fn throttle_from_headers(&self, _headers: &HeaderMap) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// Url listing the tradable symbols, if the market provides it.
+    fn symbols_url(&self) -> Option<String> {
+        None
+    }
+
+    /// Reads the tradable symbols from the `symbols_url` response.
+    fn symbols(&self, _value: &Value) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Close time of a kline opening at `open_time`, for the markets which only return the open time.
+///
+/// The kline closes 1ms before the next one opens. Without an `interval`, e.g., for klines read back
+/// from a file, the open time is returned.
+pub(super) fn close_time(open_time: DateTime<Utc>, interval: Option<Duration>) -> DateTime<Utc> {
+    interval.map_or(open_time, |interval| open_time + interval - Duration::milliseconds(1))
+}
+
+/// Builds the endpoint of the market selected by the command.
+pub fn endpoint(cmd: &Command) -> Box<dyn Endpoint<'_> + '_> {
+    match cmd.market {
+        Market::Gate => Box::new(Gate::build(cmd)),
+        Market::Binance => Box::new(Binance::build(cmd)),
+        Market::Kraken => Box::new(Kraken::build(cmd)),
+        Market::Coinbase => Box::new(Coinbase::build(cmd)),
+        Market::Bybit => Box::new(Bybit::build(cmd)),
+        Market::Okx => Box::new(Okx::build(cmd)),
+    }
+}
+
+/// Reads a JSON file of klines, auto-detecting the exchange format.
+///
+/// # Errors
+/// Returns `Error::InvalidFile` if the file doesn't match any known kline format.
+pub fn read_klines(path: &PathBuf) -> Result<Vec<Box<dyn Kline>>> {
+    if let Ok(rows) = read_data_from_file::<Value>(path) {
+        for layout in [&ArrayLayout::BINANCE, &ArrayLayout::GATE] {
+            if let Ok(data) = rows
+                .iter()
+                .map(|row| ArrayKline::from_value(row, layout, None))
+                .collect::<Result<Vec<_>>>()
+            {
+                return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+            }
+        }
+    }
+    if let Ok(data) = read_data_from_file::<KrakenKline>(path) {
+        return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+    }
+    if let Ok(data) = read_data_from_file::<CoinbaseKline>(path) {
+        return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+    }
+    if let Ok(data) = read_data_from_file::<BybitKline>(path) {
+        return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+    }
+    if let Ok(data) = read_data_from_file::<OkxKline>(path) {
+        return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+    }
+    if let Ok(data) = read_data_from_file::<Candle>(path) {
+        return Ok(data.into_iter().map(|k| Box::new(k) as Box<dyn Kline>).collect());
+    }
+
+    Err(Error::InvalidFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::scratch_dir;
+    use crate::utils::write_to_file;
+
+    /// A Binance 1h kline opening at `open_time`.
+    fn binance_row(open_time: DateTime<Utc>) -> Value {
+        let (open, close) = (open_time.timestamp_millis(), (open_time + Duration::hours(1)).timestamp_millis() - 1);
+        json!([open, "1", "2", "0.5", "1.5", "10", close, "15", 3, "4", "6", "0"])
+    }
+
+    #[test]
+    fn the_candle_of_the_current_interval_is_not_closed() {
+        let cmd = Command::new(Market::Binance, "BTCUSDT", Interval::H1);
+        let market = endpoint(&cmd);
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 10, 30, 0).unwrap();
+
+        let previous = market.kline(&binance_row(Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap())).unwrap();
+        let current = market.kline(&binance_row(Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap())).unwrap();
+        assert!(Candle::new(&previous, now).is_closed(now));
+        assert!(!Candle::new(&current, now).is_closed(now));
+
+        let row = serde_json::to_value(Candle::new(&current, now)).unwrap();
+        assert_eq!(row["is_closed"], false);
+    }
+
+    #[test]
+    fn close_time_is_one_millisecond_before_the_next_open() {
+        let open_time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            close_time(open_time, Some(Duration::hours(1))),
+            open_time + Duration::hours(1) - Duration::milliseconds(1)
+        );
+        assert_eq!(close_time(open_time, None), open_time);
+    }
+
+    #[test]
+    fn unsupported_intervals_are_told_by_the_markets() {
+        let supports = |market: Market, interval: Interval| {
+            let cmd = Command::new(market, "BTCUSDT", interval.clone());
+            endpoint(&cmd).supports(&interval)
+        };
+        assert!(!supports(Market::Bybit, Interval::S1));
+        assert!(!supports(Market::Bybit, Interval::H8));
+        assert!(supports(Market::Bybit, Interval::H4));
+        assert!(!supports(Market::Kraken, Interval::S1));
+        assert!(!supports(Market::Kraken, Interval::H2));
+        assert!(supports(Market::Kraken, Interval::W1));
+        assert!(!supports(Market::Coinbase, Interval::M3));
+        assert!(!supports(Market::Coinbase, Interval::W1));
+        assert!(supports(Market::Coinbase, Interval::H6));
+        assert!(!supports(Market::Gate, Interval::S1));
+        assert!(!supports(Market::Gate, Interval::H2));
+        assert!(!supports(Market::Gate, Interval::MM1));
+        assert!(supports(Market::Gate, Interval::H8));
+        assert!(supports(Market::Binance, Interval::S1));
+    }
+
+    #[test]
+    fn read_klines_detects_the_file_format() {
+        let dir = scratch_dir("read-klines");
+        let path = dir.join("binance.json");
+        let open_time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        write_to_file(&path, &[binance_row(open_time), binance_row(open_time + Duration::hours(1))]).unwrap();
+        let klines = read_klines(&path).unwrap();
+        assert_eq!(klines.len(), 2);
+        assert_eq!(klines[1].open_time(), open_time + Duration::hours(1));
+        assert_eq!(klines[0].trades(), Some(3));
+
+        let path = dir.join("other.json");
+        write_to_file(&path, &[json!({"time": 1})]).unwrap();
+        assert!(matches!(read_klines(&path), Err(Error::InvalidFile)));
+    }
 }
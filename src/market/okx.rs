@@ -0,0 +1,309 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_this_or_that::as_f64;
+
+use super::{ArrayLayout, Endpoint, Kline, close_time, to_datetime_millis};
+use crate::{
+    cli::{Command, Interval},
+    errors::{self, Error},
+};
+
+/// A wrapper for the OKX exchange configuration.
+pub struct Okx<'b>(&'b Command);
+
+impl<'b> Okx<'b> {
+    const BASE_URL: &'b str = "https://www.okx.com/api/v5/market/candles";
+    const HISTORY_URL: &'b str = "https://www.okx.com/api/v5/market/history-candles";
+    const TIME_URL: &'b str = "https://www.okx.com/api/v5/public/time";
+    const MAX_LIMIT: i64 = 100;
+    /// Depth of `/market/candles`, the older candles are only served by `/market/history-candles`.
+    const RECENT_CANDLES: i64 = 1440;
+
+    pub fn build(command: &'b Command) -> Self {
+        Self(command)
+    }
+
+    /// The `--base-url` override, or the built-in klines url.
+    fn base_url(&self) -> &str {
+        self.0.base_url.as_deref().unwrap_or(Self::BASE_URL)
+    }
+
+    /// OKX names the intervals like `1m` and `1H`, the daily and longer ones are aligned on UTC with the `utc` suffix.
+    fn bar(interval: &Interval) -> &'static str {
+        match interval {
+            Interval::S1 => "1s",
+            Interval::M1 => "1m",
+            Interval::M3 => "3m",
+            Interval::M5 => "5m",
+            Interval::M15 => "15m",
+            Interval::M30 => "30m",
+            Interval::H1 => "1H",
+            Interval::H2 => "2H",
+            Interval::H4 => "4H",
+            Interval::H6 => "6Hutc",
+            Interval::H8 => "8Hutc",
+            Interval::H12 => "12Hutc",
+            Interval::D1 => "1Dutc",
+            Interval::D3 => "3Dutc",
+            Interval::W1 => "1Wutc",
+            Interval::MM1 => "1Mutc",
+        }
+    }
+
+    /// The klines url of the page of candles older than `after` (milliseconds), the history one past the recent candles.
+    fn klines_url(&self, after: Option<i64>) -> String {
+        let recent = after.is_none_or(|after| {
+            // a page of margin, so no page straddles the depth of the recent candles.
+            let depth = self.0.interval.duration() * (Self::RECENT_CANDLES - 2 * self.limit()) as i32;
+            after >= (Utc::now() - depth).timestamp_millis()
+        });
+        match &self.0.base_url {
+            _ if recent => self.base_url().to_string(),
+            None => Self::HISTORY_URL.to_string(),
+            // the history is next to the candles, a `--base-url` laid out otherwise serves both.
+            Some(url) => url
+                .strip_suffix("/candles")
+                .map_or_else(|| url.clone(), |market| format!("{market}/history-candles")),
+        }
+    }
+
+    /// The url of the page of candles older than `after` (milliseconds), or of the latest candles.
+    fn page_url(&self, after: Option<i64>) -> String {
+        let symbol = self.0.symbol.to_uppercase();
+        let bar = Self::bar(&self.0.interval);
+        let url = format!("{}?instId={symbol}&bar={bar}&limit={}", self.klines_url(after), self.limit());
+        match after {
+            Some(after) => format!("{url}&after={after}"),
+            None => url,
+        }
+    }
+}
+
+impl<'b> Endpoint<'b> for Okx<'b> {
+    fn time_url(&self) -> &str {
+        Self::TIME_URL
+    }
+
+    fn server_time(&self, value: &Value) -> errors::Result<DateTime<Utc>> {
+        value["data"][0]["ts"]
+            .as_str()
+            .and_then(|ts| ts.parse().ok())
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or(Error::InvalidDatetime)
+    }
+
+    fn rate_limit(&self) -> f64 {
+        // 40 requests per 2 seconds per IP.
+        15.0
+    }
+
+    fn limit(&self) -> i64 {
+        self.0.candles_per_request(Self::MAX_LIMIT)
+    }
+
+    fn row_size(&self) -> usize {
+        110
+    }
+
+    fn rows(&self, mut body: Value) -> errors::Result<Vec<Value>> {
+        // e.g., `{"code":"51001","msg":"Instrument ID doesn't exist.","data":[]}`
+        if body["code"].as_str() != Some("0") {
+            return Err(Error::Exchange(format!(
+                "{} ({})",
+                body["msg"].as_str().unwrap_or("unknown error"),
+                body["code"].as_str().unwrap_or_default()
+            )));
+        }
+
+        match body["data"].take() {
+            // newest first, reversed to keep every market chronological.
+            Value::Array(mut rows) => {
+                rows.reverse();
+                Ok(rows)
+            }
+            _ => Err(Error::Exchange(body.to_string())),
+        }
+    }
+
+    fn kline(&self, value: &Value) -> errors::Result<Box<dyn Kline>> {
+        let mut kline = OkxKline::deserialize(value)?;
+        kline.interval = Some(self.0.interval.duration());
+        Ok(Box::new(kline))
+    }
+
+    /// The first page holds the candles right before `to_date` (excluded), or the latest ones.
+    fn urls(&self) -> Vec<String> {
+        vec![self.page_url(self.0.to_date.map(|to| to.timestamp_millis()))]
+    }
+
+    /// One page per `limit` candles of the range, the latest page only without a range.
+    fn chunks(&self) -> usize {
+        self.0
+            .last
+            .or(self.0.candles_in_range())
+            .map_or(1, |candles| candles.div_ceil(self.limit() as u64).max(1) as usize)
+    }
+
+    /// Walks back from the oldest candle of the page, until `from_date` or `--last` is reached.
+    fn next_url(&self, page: &[Value], collected: usize) -> Option<String> {
+        if page.len() < self.limit() as usize {
+            return None;
+        }
+        let oldest = page.first().and_then(|row| ArrayLayout::OKX.timestamp(row.as_array()?, 0).ok())?;
+        let reached = match (self.0.from_date, self.0.last) {
+            (Some(from), _) => oldest <= from,
+            // one more candle for the one still forming.
+            (None, Some(last)) => collected as u64 > last,
+            (None, None) => true,
+        };
+        (!reached).then(|| self.page_url(Some(oldest.timestamp_millis())))
+    }
+}
+
+/// Represents a single candlestick (kline) from OKX.
+#[derive(Debug, Deserialize)]
+pub struct OkxKline {
+    #[serde(rename = "0", deserialize_with = "to_datetime_millis")]
+    ts: DateTime<Utc>,
+    #[serde(rename = "1", deserialize_with = "as_f64")]
+    open_price: f64,
+    #[serde(rename = "2", deserialize_with = "as_f64")]
+    high_price: f64,
+    #[serde(rename = "3", deserialize_with = "as_f64")]
+    low_price: f64,
+    #[serde(rename = "4", deserialize_with = "as_f64")]
+    close_price: f64,
+    #[serde(rename = "5", deserialize_with = "as_f64")]
+    volume: f64,
+    // the unused columns are still required, so `read_klines` tells the formats apart by their length.
+    #[serde(rename = "6", deserialize_with = "as_f64")]
+    #[allow(dead_code)]
+    volume_currency: f64,
+    #[serde(rename = "7", deserialize_with = "as_f64")]
+    #[allow(dead_code)]
+    volume_currency_quote: f64,
+    /// `"0"` while the candle is still forming, `"1"` once closed.
+    #[serde(rename = "8")]
+    confirm: String,
+    /// The requested interval, OKX only returning the open time.
+    #[serde(skip)]
+    interval: Option<Duration>,
+}
+
+impl Kline for OkxKline {
+    fn open_time(&self) -> DateTime<Utc> {
+        self.ts
+    }
+
+    fn close_time(&self) -> DateTime<Utc> {
+        close_time(self.ts, self.interval)
+    }
+
+    fn open(&self) -> f64 {
+        self.open_price
+    }
+
+    fn high(&self) -> f64 {
+        self.high_price
+    }
+
+    fn low(&self) -> f64 {
+        self.low_price
+    }
+
+    fn close(&self) -> f64 {
+        self.close_price
+    }
+
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    fn is_closed(&self, _now: DateTime<Utc>) -> bool {
+        self.confirm == "1"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DurationRound, TimeDelta};
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::Market;
+
+    /// Answers like OKX: the candles older than `after`, newest first, `/market/candles` only holding the latest ones.
+    fn page(url: &str, now: i64) -> Value {
+        let after = url.split("after=").nth(1).and_then(|after| after.parse::<i64>().ok()).unwrap();
+        let depth = if url.contains("/history-candles") {
+            i64::MIN
+        } else {
+            now - Okx::RECENT_CANDLES * 60_000
+        };
+        let rows = (1..=Okx::MAX_LIMIT)
+            .map(|i| after - i * 60_000)
+            .filter(|ts| *ts >= depth)
+            .map(|ts| json!([ts.to_string(), "1", "2", "0.5", "1.5", "10", "15", "15", "1"]))
+            .collect::<Vec<_>>();
+        json!({ "code": "0", "msg": "", "data": rows })
+    }
+
+    #[test]
+    fn a_range_past_the_recent_candles_is_stitched_from_the_history() {
+        let now = Utc::now().duration_trunc(TimeDelta::minutes(1)).unwrap();
+        let mut cmd = Command::new(Market::Okx, "BTC-USDT", Interval::M1);
+        cmd.from_date = Some(now - TimeDelta::minutes(2000));
+        cmd.to_date = Some(now);
+        let market = Okx::build(&cmd);
+
+        let (mut next, mut urls, mut opens) = (market.urls().pop(), Vec::new(), Vec::new());
+        while let Some(url) = next {
+            let rows = market.rows(page(&url, now.timestamp_millis())).unwrap();
+            opens.extend(rows.iter().map(|row| ArrayLayout::OKX.timestamp(row.as_array().unwrap(), 0).unwrap()));
+            next = market.next_url(&rows, opens.len());
+            urls.push(url);
+        }
+
+        assert!(urls[0].starts_with(Okx::BASE_URL));
+        assert!(urls.last().unwrap().starts_with(Okx::HISTORY_URL));
+        opens.sort();
+        assert!(opens[0] <= now - TimeDelta::minutes(2000));
+        assert_eq!(*opens.last().unwrap(), now - TimeDelta::minutes(1));
+        assert!(
+            opens.windows(2).all(|pair| pair[1] - pair[0] == TimeDelta::minutes(1)),
+            "no gap nor duplicate"
+        );
+    }
+
+    #[test]
+    fn a_kline_closes_one_interval_after_its_open_time() {
+        let cmd = Command::new(Market::Okx, "BTC-USDT", Interval::H1);
+        let row = json!([
+            "1704067200000",
+            "42283.5",
+            "42557.6",
+            "42269.1",
+            "42475.6",
+            "149.5",
+            "149.5",
+            "6342383.5",
+            "0"
+        ]);
+        let kline = Okx::build(&cmd).kline(&row).unwrap();
+        assert_eq!(kline.close_time().timestamp_millis(), 1704070799999);
+        // still forming, whatever the time.
+        assert!(!kline.is_closed(kline.close_time() + Duration::days(1)));
+    }
+
+    #[test]
+    fn the_history_is_next_to_the_base_url() {
+        let mut cmd = Command::new(Market::Okx, "BTC-USDT", Interval::M1);
+        let old = Some((Utc::now() - TimeDelta::days(30)).timestamp_millis());
+        cmd.base_url = Some("http://127.0.0.1:8080/api/v5/market/candles".to_string());
+        assert_eq!(Okx::build(&cmd).klines_url(old), "http://127.0.0.1:8080/api/v5/market/history-candles");
+        assert_eq!(Okx::build(&cmd).klines_url(None), "http://127.0.0.1:8080/api/v5/market/candles");
+        cmd.base_url = Some("http://127.0.0.1:8080/klines".to_string());
+        assert_eq!(Okx::build(&cmd).klines_url(old), "http://127.0.0.1:8080/klines");
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+
+use crate::cli::{Command, ProgressFormat};
+use crate::errors::{Error, Result};
+
+/// Reports the download progress, once per completed url.
+pub enum Progress {
+    /// Nothing is reported but the errors.
+    Hidden,
+    /// A human readable progress bar, of the downloaded urls.
+    Bar(ProgressBar),
+    /// A human readable progress bar, of the collected candles.
+    Candles(ProgressBar),
+    /// One JSON event per completed url, printed to stderr.
+    Json {
+        total: usize,
+        started: Instant,
+        /// Completed urls so far, across every symbol.
+        done: AtomicUsize,
+        /// Collected candles so far, across every symbol.
+        candles: AtomicUsize,
+    },
+}
+
+impl Progress {
+    /// Builds the progress reporter selected by the command for `total` urls, returning about `candles` candles.
+    pub fn build(cmd: &Command, total: usize, candles: u64) -> Result<Self> {
+        match cmd.progress_format {
+            _ if cmd.quiet => Ok(Progress::Hidden),
+            ProgressFormat::Json => Ok(Progress::Json {
+                total,
+                started: Instant::now(),
+                done: AtomicUsize::new(0),
+                candles: AtomicUsize::new(0),
+            }),
+            // the bar would garble the klines printed to stdout.
+            ProgressFormat::Bar if cmd.verbose && !cmd.stdout => Ok(Progress::Bar(bar(total as u64, "")?)),
+            ProgressFormat::Candles if cmd.verbose && !cmd.stdout => Ok(Progress::Candles(bar(candles, " candles")?)),
+            ProgressFormat::Bar | ProgressFormat::Candles => Ok(Progress::Hidden),
+        }
+    }
+
+    /// Reports a completed url.
+    ///
+    /// # Arguments
+    /// * `rows` - The number of candles the url returned.
+    pub fn chunk_done(&self, rows: usize) {
+        match self {
+            Progress::Hidden => (),
+            Progress::Bar(pb) => pb.inc(1),
+            Progress::Candles(pb) => pb.inc(rows as u64),
+            Progress::Json {
+                total,
+                started,
+                done,
+                candles,
+            } => {
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                let candles = candles.fetch_add(rows, Ordering::Relaxed) + rows;
+                let elapsed_ms = started.elapsed().as_millis() as u64;
+                eprintln!(
+                    "{}",
+                    json!({ "done": done, "total": total, "candles": candles, "elapsed_ms": elapsed_ms })
+                );
+            }
+        }
+    }
+
+    /// Reports a failed url.
+    pub fn chunk_failed(&self, error: &Error) {
+        match self {
+            Progress::Hidden => eprintln!("{error}"),
+            Progress::Bar(pb) | Progress::Candles(pb) => pb.abandon_with_message(error.to_string()),
+            Progress::Json { .. } => eprintln!("{}", json!({ "error": error.to_string() })),
+        }
+    }
+
+    /// Reports the end of the download.
+    pub fn finish(&self, message: &str) {
+        match self {
+            Progress::Bar(pb) | Progress::Candles(pb) => pb.finish_with_message(message.to_string()),
+            _ => println!("{message}"),
+        }
+    }
+}
+
+/// Builds a progress bar of `len` steps, `unit` following the position.
+fn bar(len: u64, unit: &str) -> Result<ProgressBar> {
+    let pb = ProgressBar::new(len);
+    let template = format!("{{spinner:.green}} [{{elapsed_precise}}] [{{wide_bar:.cyan/blue}}] {{pos}}/{{len}}{unit} ({{eta}})\n{{msg}}");
+    let style = ProgressStyle::with_template(&template).map_err(Error::from)?.progress_chars("#>-");
+    pb.set_style(style);
+    Ok(pb)
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+use crate::cli::Interval;
+use crate::market::{Candle, Kline};
+
+/// Start of the `interval` bucket holding `time`.
+///
+/// Weeks start on Monday and months on their first day, like on the exchanges. The other
+/// intervals are aligned on the Unix epoch.
+fn bucket_start(time: DateTime<Utc>, interval: &Interval) -> DateTime<Utc> {
+    match interval {
+        Interval::MM1 => Utc.with_ymd_and_hms(time.year(), time.month(), 1, 0, 0, 0).unwrap(),
+        _ => {
+            // the epoch is a Thursday, weeks are aligned on the first Monday after it.
+            let offset = if matches!(interval, Interval::W1) {
+                Duration::days(4)
+            } else {
+                Duration::zero()
+            };
+            let step = interval.duration().num_milliseconds();
+            let elapsed = (time - offset).timestamp_millis();
+            DateTime::from_timestamp_millis(elapsed - elapsed.rem_euclid(step)).unwrap() + offset
+        }
+    }
+}
+
+/// End of the `interval` bucket starting at `start`, i.e., the start of the next one.
+fn bucket_end(start: DateTime<Utc>, interval: &Interval) -> DateTime<Utc> {
+    match interval {
+        Interval::MM1 => {
+            let (year, month) = if start.month() == 12 {
+                (start.year() + 1, 1)
+            } else {
+                (start.year(), start.month() + 1)
+            };
+            Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap()
+        }
+        _ => start + interval.duration(),
+    }
+}
+
+/// Whether klines spaced by `spacing` can be grouped into `interval` buckets, i.e., `interval` is a multiple of it.
+pub fn can_resample(spacing: Duration, interval: &Interval) -> bool {
+    // months have 28 to 31 days, a whole number of days in all cases.
+    let span = match interval {
+        Interval::MM1 => Duration::days(1),
+        _ => interval.duration(),
+    };
+    spacing > Duration::zero() && spacing <= span && span.num_milliseconds() % spacing.num_milliseconds() == 0
+}
+
+/// Groups chronological klines into `interval` buckets, aggregating each bucket into a single candle.
+///
+/// The buckets of the missing klines are skipped, and the last bucket may be partial.
+pub fn resample<T: Kline>(data: &[T], interval: &Interval, now: DateTime<Utc>) -> Vec<Candle> {
+    data.chunk_by(|a, b| bucket_start(a.open_time(), interval) == bucket_start(b.open_time(), interval))
+        .filter_map(|bucket| {
+            let start = bucket_start(bucket[0].open_time(), interval);
+            let close_time = bucket_end(start, interval) - Duration::milliseconds(1);
+            Candle::aggregate(bucket, start, close_time, now)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A 1m candle opening at `open_time`, with `[open, high, low, close]` prices and a volume of 10.
+    fn candle(open_time: DateTime<Utc>, [open, high, low, close]: [f64; 4]) -> Candle {
+        serde_json::from_value(json!({
+            "open_time": open_time,
+            "open": open,
+            "high": high,
+            "low": low,
+            "close": close,
+            "volume": 10.0,
+            "close_time": open_time + Duration::minutes(1) - Duration::milliseconds(1),
+            "is_closed": true,
+            "trades": 2,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn buckets_aggregate_their_klines() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let klines = (0..7)
+            .map(|i| {
+                candle(
+                    start + Duration::minutes(i),
+                    [i as f64, 10.0 + i as f64, 1.0 - i as f64 / 10.0, i as f64 + 0.5],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let candles = resample(&klines, &Interval::M5, start + Duration::days(1));
+        assert_eq!(candles.len(), 2);
+        let (first, last) = (&candles[0], &candles[1]);
+        assert_eq!(
+            (first.open_time(), first.close_time()),
+            (start, start + Duration::minutes(5) - Duration::milliseconds(1))
+        );
+        assert_eq!([first.open(), first.high(), first.low(), first.close()], [0.0, 14.0, 0.6, 4.5]);
+        assert_eq!((first.volume(), first.trades()), (50.0, Some(10)));
+        // the last bucket is partial.
+        assert_eq!(last.open_time(), start + Duration::minutes(5));
+        assert_eq!([last.open(), last.close(), last.volume()], [5.0, 6.5, 20.0]);
+    }
+
+    #[test]
+    fn weeks_start_on_monday_and_months_on_their_first_day() {
+        // a Wednesday.
+        let time = Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap();
+        assert_eq!(bucket_start(time, &Interval::W1), Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(bucket_start(time, &Interval::MM1), Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(
+            bucket_end(Utc.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap(), &Interval::MM1),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn only_multiples_of_the_spacing_can_be_resampled() {
+        assert!(can_resample(Duration::minutes(1), &Interval::H1));
+        assert!(can_resample(Duration::hours(1), &Interval::MM1));
+        assert!(!can_resample(Duration::minutes(5), &Interval::M3));
+        // upsampling.
+        assert!(!can_resample(Duration::hours(1), &Interval::M15));
+    }
+}
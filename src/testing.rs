@@ -0,0 +1,135 @@
+//! Helpers shared by the unit tests: scratch directories and a local HTTP server standing in for an exchange.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// An empty directory for the files of the test `name`, under the system temporary directory.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("download-ticks-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("the scratch directory can be created");
+    dir
+}
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// The path and query of the request (e.g., `/klines?symbol=BTCUSDT`).
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// The value of the header `name`, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The response the mock server answers a request with.
+pub struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+}
+
+impl Reply {
+    /// A `200 OK` response holding `body`.
+    pub fn json(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Waits `delay` before sending the response.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// An HTTP/1.1 server answering every request with the reply of a handler, one connection per request.
+pub struct MockServer {
+    /// The base url of the server (e.g., `http://127.0.0.1:40000`).
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    /// Starts a server on a free local port, answering with the `reply` of each request.
+    pub async fn start<F>(reply: F) -> Self
+    where
+        F: Fn(&Request) -> Reply + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("a local port is free");
+        let url = format!("http://{}", listener.local_addr().expect("the server has an address"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (reply, received) = (Arc::new(reply), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (reply, received) = (reply.clone(), received.clone());
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => head.extend_from_slice(&buffer[..n]),
+                        }
+                    }
+                    let head = String::from_utf8_lossy(&head);
+                    let mut lines = head.split("\r\n");
+                    let path = lines.next().and_then(|line| line.split(' ').nth(1)).unwrap_or_default().to_string();
+                    let headers = lines
+                        .filter_map(|line| line.split_once(':'))
+                        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                        .collect();
+                    let request = Request { path, headers };
+                    let response = reply(&request);
+                    received.lock().unwrap().push(request);
+
+                    tokio::time::sleep(response.delay).await;
+                    let mut message = format!(
+                        "HTTP/1.1 {} MOCK\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        message.push_str(&format!("{name}: {value}\r\n"));
+                    }
+                    message.push_str("\r\n");
+                    let _ = stream.write_all(message.as_bytes()).await;
+                    let _ = stream.write_all(&response.body).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// The requests received so far, in their order of arrival.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
@@ -1,44 +1,45 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use chrono::{DateTime, Duration, Utc};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, TimestampMillisecondArray, UInt64Array};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::{bufread::GzDecoder, write::GzEncoder};
+use futures::TryStreamExt;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use reqwest::Response;
+use rusqlite::{Connection, params};
 use serde::{Serialize, de::DeserializeOwned};
-use serde_json::{from_reader, to_writer};
+use serde_json::{Deserializer, from_reader, from_slice, to_writer, to_writer_pretty};
+use tokio::{sync::Mutex, time::Instant};
+use tokio_util::io::{StreamReader, SyncIoBridge};
 
-use crate::{Error, Interval, Result};
+use crate::{Error, Interval, Kline, Market, Result, cli::StdoutFormat};
 
-/// Splits a time range into intervals suitable for Binance's API (max 999 candles per request).
+/// Splits a time range into intervals suitable for the exchanges' APIs (max `limit` candles per request).
 ///
 /// # Arguments
 /// * `start` - Start date of the range.
 /// * `end` - End date of the range.
 /// * `interval` - The time interval (m1, h1, d1).
+/// * `limit` - The maximum number of candles the exchange returns per request.
 ///
 /// # Returns
 /// A vector of tuples `(start, end)` representing the split intervals.
-pub fn split_intervals(start: DateTime<Utc>, end: DateTime<Utc>, interval: &Interval) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+pub fn split_intervals(start: DateTime<Utc>, end: DateTime<Utc>, interval: &Interval, limit: i64) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
     let mut intervals = Vec::new();
-    let mut current_start = start;
-
-    // maximum duration for 999 candles, based on the interval.
-    // also return the interval duration to increment `current_start`.
-    let (max_duration, plus_duration) = match interval {
-        Interval::S1 => (Duration::seconds(999), Duration::seconds(1)),
-        Interval::M1 => (Duration::minutes(999), Duration::minutes(1)),
-        Interval::M3 => (Duration::minutes(999), Duration::minutes(3)),
-        Interval::M5 => (Duration::minutes(999), Duration::minutes(5)),
-        Interval::M15 => (Duration::minutes(999), Duration::minutes(15)),
-        Interval::M30 => (Duration::minutes(999), Duration::minutes(30)),
-        Interval::H1 => (Duration::hours(999), Duration::hours(1)),
-        Interval::H2 => (Duration::hours(999), Duration::hours(2)),
-        Interval::H4 => (Duration::hours(999), Duration::hours(4)),
-        Interval::H6 => (Duration::hours(999), Duration::hours(6)),
-        Interval::H8 => (Duration::hours(999), Duration::hours(8)),
-        Interval::H12 => (Duration::hours(999), Duration::hours(12)),
-        Interval::D1 => (Duration::days(999), Duration::days(1)),
-        Interval::D3 => (Duration::days(999), Duration::days(3)),
-        Interval::W1 => (Duration::weeks(999), Duration::weeks(1)),
-        Interval::MM1 => (Duration::weeks(999), Duration::weeks(4)),
-    };
+    // the markets return the candles opening from the chunk start, so an unaligned start (e.g., `7d`)
+    // would skip the candle opening right before each next chunk.
+    let mut current_start = interval.floor(start);
+
+    // both ends of a chunk are inclusive, so `limit` candles span `limit - 1` intervals.
+    // also use the interval duration to increment `current_start`.
+    let plus_duration = interval.duration();
+    let max_duration = plus_duration * (limit - 1) as i32;
 
     while current_start < end {
         let current_end = std::cmp::min(current_start + max_duration, end);
@@ -49,33 +50,625 @@ pub fn split_intervals(start: DateTime<Utc>, end: DateTime<Utc>, interval: &Inte
     intervals
 }
 
-/// Reads candlestick data from a file containing serialized Kline data.
+/// Whether the file is gzip compressed, from its `.gz` extension.
+fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Reads candlestick data from a file containing serialized Kline data, as a JSON array or one kline per line (NDJSON).
+///
+/// Files ending in `.gz` are decompressed on the fly.
 pub fn read_data_from_file<T>(path: &PathBuf) -> Result<Vec<T>>
 where
     T: DeserializeOwned,
 {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    from_reader(reader).map_err(Error::from)
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut content = Vec::new();
+    if is_gzip(path) {
+        GzDecoder::new(reader).read_to_end(&mut content)?;
+    } else {
+        reader.read_to_end(&mut content)?;
+    }
+
+    // the raw klines are arrays too, so the first byte can't tell an array of klines from NDJSON.
+    from_slice(&content)
+        .or_else(|_| Deserializer::from_slice(&content).into_iter().collect::<serde_json::Result<_>>())
+        .map_err(Error::from)
+}
+
+/// Spaces out requests so they never exceed a given rate.
+pub struct Throttle {
+    spacing: std::time::Duration,
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    /// Builds a throttle leaving at least `spacing` between two requests.
+    pub fn new(spacing: std::time::Duration) -> Self {
+        Self {
+            spacing,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot.
+    ///
+    /// Slots are booked before sleeping, so concurrent callers are released one `spacing` apart.
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.spacing;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Holds back every request not released yet for `pause`.
+    pub async fn pause(&self, pause: std::time::Duration) {
+        let mut next = self.next.lock().await;
+        *next = (*next).max(Instant::now() + pause);
+    }
+}
+
+/// Parses a `Retry-After` header, given as a number of seconds or an HTTP date.
+///
+/// # Returns
+/// The delay to wait, zero for a date in the past, `None` if the value can't be parsed.
+pub fn retry_after(value: &str) -> Option<std::time::Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Deserializes a response body while it's being downloaded.
+///
+/// Unlike `Response::json`, the body is never buffered as a whole: chunks are fed to the JSON parser
+/// as they arrive, so the peak memory is roughly the size of the parsed rows.
+pub async fn read_json<T>(response: Response) -> Result<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let body = response.bytes_stream().map_err(std::io::Error::other);
+    let reader = SyncIoBridge::new(StreamReader::new(body));
+    tokio::task::spawn_blocking(move || from_reader(BufReader::new(reader)).map_err(Error::from)).await?
 }
 
 /// Writes candlestick data to a file.
+///
+/// Files ending in `.gz` are gzip compressed, the content stays JSON.
 pub fn write_to_file<T>(path: &PathBuf, klines: &[T]) -> Result<()>
 where
     T: Serialize,
 {
     let file = File::create(path)?;
+    if is_gzip(path) {
+        let mut encoder = GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+        to_writer(&mut encoder, &klines)?;
+        encoder.finish()?.flush()?;
+        return Ok(());
+    }
     to_writer(file, &klines).map_err(Error::from)
 }
 
-/// Number separator
+/// Writes candlestick data to a file, one JSON kline per line (NDJSON).
+///
+/// Files ending in `.gz` are gzip compressed.
+pub fn write_ndjson<T>(path: &PathBuf, klines: &[T]) -> Result<()>
+where
+    T: Serialize,
+{
+    let file = BufWriter::new(File::create(path)?);
+    if is_gzip(path) {
+        let mut encoder = GzEncoder::new(file, flate2::Compression::default());
+        write_lines(&mut encoder, klines)?;
+        encoder.finish()?.flush()?;
+        return Ok(());
+    }
+    write_lines(file, klines)
+}
+
+/// Writes one JSON kline per line to `writer`.
+pub fn write_lines<T, W>(mut writer: W, klines: &[T]) -> Result<()>
+where
+    T: Serialize,
+    W: Write,
+{
+    for kline in klines {
+        to_writer(&mut writer, kline)?;
+        writeln!(writer)?;
+    }
+    writer.flush().map_err(Error::from)
+}
+
+/// Writes the klines to `writer` in the `--stdout` format.
+pub fn write_stdout<T, W>(mut writer: W, format: &StdoutFormat, klines: &[T]) -> Result<()>
+where
+    T: Serialize,
+    W: Write,
+{
+    match format {
+        StdoutFormat::Ndjson => return write_lines(writer, klines),
+        StdoutFormat::Json => to_writer(&mut writer, klines)?,
+        StdoutFormat::Pretty => to_writer_pretty(&mut writer, klines)?,
+    }
+    writeln!(writer)?;
+    writer.flush().map_err(Error::from)
+}
+
+/// Writes candlestick data to a CSV file, with RFC 3339 timestamps.
+///
+/// The `vwap` and `net_taker_volume` columns are only written when the klines provide them.
+pub fn write_csv<T: Kline>(path: &PathBuf, klines: &[T]) -> Result<()> {
+    let with_vwap = klines.first().is_some_and(|kline| kline.vwap().is_some());
+    let with_net_taker = klines.first().is_some_and(|kline| kline.net_taker_volume().is_some());
+    let time = |datetime: DateTime<Utc>| datetime.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "open_time,open,high,low,close,volume,close_time")?;
+    if with_vwap {
+        write!(writer, ",vwap")?;
+    }
+    if with_net_taker {
+        write!(writer, ",net_taker_volume")?;
+    }
+    writeln!(writer)?;
+
+    for kline in klines {
+        write!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            time(kline.open_time()),
+            kline.open(),
+            kline.high(),
+            kline.low(),
+            kline.close(),
+            kline.volume(),
+            time(kline.close_time())
+        )?;
+        if with_vwap {
+            write!(writer, ",{}", optional(kline.vwap()))?;
+        }
+        if with_net_taker {
+            write!(writer, ",{}", optional(kline.net_taker_volume()))?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush().map_err(Error::from)
+}
+
+/// Writes candlestick data to a Snappy compressed Parquet file.
+///
+/// Timestamps are typed as `timestamp(ms, UTC)`, prices and volume as `float64`,
+/// and the number of trades as a nullable `uint64`.
+pub fn write_parquet<T: Kline>(path: &PathBuf, klines: &[T]) -> Result<()> {
+    let timestamps = |f: fn(&T) -> DateTime<Utc>| {
+        let values = klines.iter().map(|kline| f(kline).timestamp_millis());
+        Arc::new(TimestampMillisecondArray::from_iter_values(values).with_timezone("UTC")) as ArrayRef
+    };
+    let floats = |f: fn(&T) -> f64| Arc::new(Float64Array::from_iter_values(klines.iter().map(f))) as ArrayRef;
+
+    let batch = RecordBatch::try_from_iter([
+        ("open_time", timestamps(T::open_time)),
+        ("open", floats(T::open)),
+        ("high", floats(T::high)),
+        ("low", floats(T::low)),
+        ("close", floats(T::close)),
+        ("volume", floats(T::volume)),
+        ("close_time", timestamps(T::close_time)),
+        ("trades", Arc::new(klines.iter().map(T::trades).collect::<UInt64Array>()) as ArrayRef),
+    ])?;
+
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Inserts candlestick data into the `klines` table of a SQLite database, created if missing.
+///
+/// The rows are keyed on `(symbol, interval, open_time)` and the existing ones are updated,
+/// so re-running a download is idempotent and the candles saved while forming get their closed values.
+/// Timestamps are stored in milliseconds.
+pub fn write_sqlite<T: Kline>(path: &PathBuf, symbol: &str, interval: &Interval, klines: &[T]) -> Result<()> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS klines (
+            symbol TEXT NOT NULL,
+            interval TEXT NOT NULL,
+            open_time INTEGER NOT NULL,
+            open REAL NOT NULL,
+            high REAL NOT NULL,
+            low REAL NOT NULL,
+            close REAL NOT NULL,
+            volume REAL NOT NULL,
+            close_time INTEGER NOT NULL,
+            vwap REAL,
+            trades INTEGER,
+            PRIMARY KEY (symbol, interval, open_time)
+        )",
+    )?;
+
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO klines (symbol, interval, open_time, open, high, low, close, volume, close_time, vwap, trades)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            ON CONFLICT (symbol, interval, open_time) DO UPDATE SET
+                open = excluded.open, high = excluded.high, low = excluded.low, close = excluded.close,
+                volume = excluded.volume, close_time = excluded.close_time, vwap = excluded.vwap, trades = excluded.trades",
+        )?;
+        let interval = interval.to_string();
+        for kline in klines {
+            insert.execute(params![
+                symbol,
+                interval,
+                kline.open_time().timestamp_millis(),
+                kline.open(),
+                kline.high(),
+                kline.low(),
+                kline.close(),
+                kline.volume(),
+                kline.close_time().timestamp_millis(),
+                kline.vwap(),
+                kline.trades().map(|trades| trades as i64),
+            ])?;
+        }
+    }
+    transaction.commit().map_err(Error::from)
+}
+
+/// Builds a path next to `path`, tagging its file name with `tag` and `extension` (e.g., `output.csv` -> `output.funding.json`).
+///
+/// A `.gz` extension is kept, so the sibling is compressed like `path`.
+pub fn sibling_path(path: &Path, tag: &str, extension: &str) -> PathBuf {
+    let base = if is_gzip(path) { path.with_extension("") } else { path.to_path_buf() };
+    let stem = base.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let gzip = if is_gzip(path) { ".gz" } else { "" };
+    path.with_file_name(format!("{stem}.{tag}.{extension}{gzip}"))
+}
+
+/// Formats a size in bytes with a binary unit (e.g., `1.5 MiB`).
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Converts a symbol written with `/`, `-` or `_` between its assets (e.g., `BTC/USDT`) to the market format:
+/// `BTCUSDT` for Binance, Bybit and Kraken, `BTC_USDT` for Gate and `BTC-USDT` for Coinbase and OKX.
+///
+/// Symbols without separator are returned as given.
+pub fn normalize_symbol(symbol: &str, market: &Market) -> String {
+    let separator = match market {
+        Market::Binance | Market::Bybit | Market::Kraken => "",
+        Market::Gate => "_",
+        Market::Coinbase | Market::Okx => "-",
+    };
+    symbol.split(['/', '-', '_']).collect::<Vec<_>>().join(separator)
+}
+
+/// Finds the candidate closest to `target`, by the case-insensitive edit distance.
+pub fn closest<'c>(target: &str, candidates: &'c [String]) -> Option<&'c str> {
+    let distance = |a: &str, b: &str| {
+        let (a, b) = (a.to_lowercase().chars().collect::<Vec<_>>(), b.to_lowercase().chars().collect::<Vec<_>>());
+        let mut previous = (0..=b.len()).collect::<Vec<_>>();
+        for (i, ca) in a.iter().enumerate() {
+            let mut current = vec![i + 1; b.len() + 1];
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(ca != cb);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            previous = current;
+        }
+        previous[b.len()]
+    };
+    candidates.iter().min_by_key(|candidate| distance(target, candidate)).map(String::as_str)
+}
+
+/// Number separator, grouping the digits of an integer by thousands (e.g., `-1234567` -> `-1_234_567`).
+///
+/// The sign is kept out of the groups, and zero is written `0`.
 pub fn separator<T: ToString>(num: T, sep: &str) -> Result<String> {
-    num.to_string()
+    let num = num.to_string();
+    let (sign, digits) = num.strip_prefix('-').map_or(("", num.as_str()), |digits| ("-", digits));
+    digits
         .as_bytes()
         .rchunks(3)
         .rev()
         .map(std::str::from_utf8)
         .collect::<std::result::Result<Vec<&str>, _>>()
-        .map(|num_sep| num_sep.join(sep))
+        .map(|num_sep| format!("{sign}{}", num_sep.join(sep)))
         .map_err(Error::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Array;
+    use arrow_schema::{DataType, TimeUnit};
+    use chrono::{Duration, TimeZone};
+    use clap::ValueEnum;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::market::Candle;
+    use crate::testing::{MockServer, Reply, scratch_dir};
+
+    #[test]
+    fn split_intervals_chunks_every_interval_by_limit() {
+        let (start, end) = (
+            Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+        );
+        let limit = 1000;
+        for interval in Interval::value_variants() {
+            let step = interval.duration();
+            let chunks = split_intervals(start, end, interval, limit);
+
+            // both ends being inclusive, the range holds one candle more than its number of steps.
+            let first = interval.floor(start);
+            let candles = ((end - first).num_seconds() / step.num_seconds() + 1) as u64;
+            assert_eq!(chunks.len() as u64, candles.div_ceil(limit as u64), "{interval}");
+            assert_eq!(chunks.first().map(|chunk| chunk.0), Some(first), "{interval}");
+            assert_eq!(chunks.last().map(|chunk| chunk.1), Some(end), "{interval}");
+            for (chunk_start, chunk_end) in &chunks {
+                assert!(*chunk_end - *chunk_start <= step * (limit - 1) as i32, "{interval}");
+            }
+            // contiguous without overlapping, the next chunk opening one interval after the previous one closes.
+            for pair in chunks.windows(2) {
+                assert_eq!(pair[1].0, pair[0].1 + step, "{interval}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_chunks_of_an_unaligned_range_cover_every_open_time() {
+        // e.g., `--from-date 7d` or `--count`, computed from the current time.
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::milliseconds(12_345_678);
+        let end = start + Duration::minutes(25);
+        let chunks = split_intervals(start, end, &Interval::M1, 10);
+        assert_eq!(chunks[0].0, Utc.with_ymd_and_hms(2024, 1, 1, 3, 25, 0).unwrap());
+
+        // like the markets, a chunk holds the candles opening from its start through its end.
+        let minute = Duration::minutes(1);
+        let served = chunks
+            .iter()
+            .flat_map(|(chunk_start, chunk_end)| {
+                let first = Interval::M1.floor(*chunk_start + minute - Duration::milliseconds(1));
+                (0..).map(move |i| first + minute * i).take_while(move |open| open <= chunk_end)
+            })
+            .filter(|open| *open + minute > start && *open < end)
+            .collect::<Vec<_>>();
+        let expected = (0..26).map(|i| Interval::M1.floor(start) + minute * i).collect::<Vec<_>>();
+        assert_eq!(served, expected);
+    }
+
+    #[test]
+    fn weeks_and_months_are_floored_to_their_calendar_start() {
+        let time = Utc.with_ymd_and_hms(2024, 2, 15, 13, 30, 0).unwrap();
+        assert_eq!(Interval::W1.floor(time), Utc.with_ymd_and_hms(2024, 2, 12, 0, 0, 0).unwrap());
+        assert_eq!(Interval::MM1.floor(time), Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap());
+        assert_eq!(Interval::H4.floor(time), Utc.with_ymd_and_hms(2024, 2, 15, 12, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn split_intervals_returns_nothing_for_an_empty_range() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert!(split_intervals(start, start, &Interval::H1, 1000).is_empty());
+    }
+
+    #[test]
+    fn separator_handles_zero_negative_and_large_values() {
+        assert_eq!(separator(0, "_").unwrap(), "0");
+        assert_eq!(separator(999, "_").unwrap(), "999");
+        assert_eq!(separator(1_234_567, "_").unwrap(), "1_234_567");
+        assert_eq!(separator(-12, "_").unwrap(), "-12");
+        assert_eq!(separator(-123_456, "_").unwrap(), "-123_456");
+        assert_eq!(separator(i64::MIN, "_").unwrap(), "-9_223_372_036_854_775_808");
+        assert_eq!(separator(u64::MAX, ",").unwrap(), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn normalize_symbol_follows_each_market_format() {
+        let expected = [
+            (Market::Binance, "BTCUSDT"),
+            (Market::Bybit, "BTCUSDT"),
+            (Market::Kraken, "BTCUSDT"),
+            (Market::Gate, "BTC_USDT"),
+            (Market::Coinbase, "BTC-USDT"),
+            (Market::Okx, "BTC-USDT"),
+        ];
+        for (market, symbol) in expected {
+            for given in ["BTC/USDT", "BTC-USDT", "BTC_USDT"] {
+                assert_eq!(normalize_symbol(given, &market), symbol, "{given} on {market}");
+            }
+        }
+        assert_eq!(normalize_symbol("BTCUSDT", &Market::Gate), "BTCUSDT");
+    }
+
+    #[test]
+    fn sibling_path_uses_the_given_extension() {
+        assert_eq!(
+            sibling_path(Path::new("out/klines.csv"), "funding", "json"),
+            PathBuf::from("out/klines.funding.json")
+        );
+        assert_eq!(
+            sibling_path(Path::new("klines.json.gz"), "funding", "json"),
+            PathBuf::from("klines.funding.json.gz")
+        );
+        assert_eq!(sibling_path(Path::new("klines"), "funding", "json"), PathBuf::from("klines.funding.json"));
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_dates() {
+        assert_eq!(retry_after("2"), Some(std::time::Duration::from_secs(2)));
+        assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(std::time::Duration::ZERO));
+        assert_eq!(retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn throttle_spaces_requests_at_the_rate() {
+        // 20 requests per second, the first one being released right away.
+        let throttle = Throttle::new(std::time::Duration::from_secs_f64(1.0 / 20.0));
+        let started = Instant::now();
+        for _ in 0..11 {
+            throttle.wait().await;
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= std::time::Duration::from_millis(500), "{elapsed:?}");
+        assert!(elapsed < std::time::Duration::from_millis(1500), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn throttle_pause_holds_back_the_next_request() {
+        let throttle = Throttle::new(std::time::Duration::ZERO);
+        throttle.pause(std::time::Duration::from_millis(300)).await;
+        let started = Instant::now();
+        throttle.wait().await;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn read_json_parses_a_large_body_while_streaming() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let rows = (0..50_000)
+            .map(|i| {
+                let open_time = (start + Duration::minutes(i)).timestamp_millis();
+                format!(r#"[{open_time},"42000.1","42010.5","41990.2","42005.3","12.345",{}]"#, open_time + 59_999)
+            })
+            .collect::<Vec<_>>();
+        let body = format!("[{}]", rows.join(","));
+        let server = MockServer::start(move |_| Reply::json(body.clone())).await;
+
+        let response = reqwest::get(&server.url).await.unwrap();
+        let rows = read_json::<Vec<Value>>(response).await.unwrap();
+        assert_eq!(rows.len(), 50_000);
+        assert_eq!(rows[49_999][0], (start + Duration::minutes(49_999)).timestamp_millis());
+    }
+
+    #[tokio::test]
+    async fn read_json_fails_on_a_truncated_body() {
+        let server = MockServer::start(|_| Reply::json(r#"[[1704067200000,"1","2""#)).await;
+        let response = reqwest::get(&server.url).await.unwrap();
+        assert!(read_json::<Vec<Value>>(response).await.is_err());
+    }
+
+    /// A closed 1m candle, opening `minute` minutes after 2024-01-01 00:00.
+    fn candle(minute: u32, close: f64) -> Candle {
+        let open_time = Utc.with_ymd_and_hms(2024, 1, 1, 0, minute, 0).unwrap();
+        serde_json::from_value::<Candle>(json!({
+            "open_time": open_time,
+            "open": 1.0,
+            "high": 2.0,
+            "low": 0.5,
+            "close": close,
+            "volume": 10.0,
+            "close_time": open_time + Duration::minutes(1) - Duration::milliseconds(1),
+            "is_closed": true,
+        }))
+        .unwrap()
+    }
+
+    /// Two closed 1m candles, from 2024-01-01 00:00.
+    fn candles() -> Vec<Candle> {
+        vec![candle(0, 1.5), candle(1, 1.25)]
+    }
+
+    #[test]
+    fn sqlite_rows_are_updated_by_a_later_run() {
+        let path = scratch_dir("sqlite").join("klines.sqlite");
+        write_sqlite(&path, "BTCUSDT", &Interval::M1, &candles()).unwrap();
+        // the last candle was saved while forming, and is closed at another price.
+        write_sqlite(&path, "BTCUSDT", &Interval::M1, &[candle(1, 1.75), candle(2, 1.5)]).unwrap();
+
+        let database = Connection::open(&path).unwrap();
+        let closes = database
+            .prepare("SELECT close FROM klines WHERE symbol = 'BTCUSDT' AND interval = '1m' ORDER BY open_time")
+            .unwrap()
+            .query_map([], |row| row.get::<_, f64>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(closes, vec![1.5, 1.75, 1.5]);
+    }
+
+    #[test]
+    fn csv_rows_hold_rfc_3339_timestamps() {
+        let path = scratch_dir("csv").join("klines.csv");
+        write_csv(&path, &candles()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content.lines().collect::<Vec<_>>(),
+            vec![
+                "open_time,open,high,low,close,volume,close_time",
+                "2024-01-01T00:00:00.000Z,1,2,0.5,1.5,10,2024-01-01T00:00:59.999Z",
+                "2024-01-01T00:01:00.000Z,1,2,0.5,1.25,10,2024-01-01T00:01:59.999Z",
+            ]
+        );
+    }
+
+    #[test]
+    fn parquet_columns_are_typed() {
+        let path = scratch_dir("parquet").join("klines.parquet");
+        write_parquet(&path, &candles()).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 2);
+        let schema = batches[0].schema();
+        let names = schema.fields().iter().map(|field| field.name().as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["open_time", "open", "high", "low", "close", "volume", "close_time", "trades"]);
+        assert_eq!(
+            schema.field(0).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+        );
+        let closes = batches[0].column(4).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(closes.values(), &[1.5, 1.25]);
+    }
+
+    #[test]
+    fn gz_files_are_compressed_and_read_back() {
+        let dir = scratch_dir("gzip");
+        let rows = vec![json!([1704067200000_i64, "1.5"]), json!([1704067260000_i64, "1.25"])];
+        for (path, write) in [
+            (dir.join("klines.json.gz"), write_to_file::<Value> as fn(&PathBuf, &[Value]) -> Result<()>),
+            (dir.join("klines.ndjson.gz"), write_ndjson::<Value>),
+        ] {
+            write(&path, &rows).unwrap();
+            // the gzip magic number.
+            assert_eq!(std::fs::read(&path).unwrap()[..2], [0x1f, 0x8b], "{}", path.display());
+            assert_eq!(read_data_from_file::<Value>(&path).unwrap(), rows, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn ndjson_files_hold_one_kline_per_line() {
+        let path = scratch_dir("ndjson").join("klines.ndjson");
+        write_ndjson(&path, &candles()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| serde_json::from_str::<Candle>(line).is_ok()), "{content}");
+        // the arrays of the raw klines are told apart from an array of klines.
+        let arrays = scratch_dir("ndjson-arrays").join("klines.ndjson");
+        write_ndjson(&arrays, &[json!([1, "a"]), json!([2, "b"])]).unwrap();
+        assert_eq!(read_data_from_file::<Value>(&arrays).unwrap(), vec![json!([1, "a"]), json!([2, "b"])]);
+    }
+}
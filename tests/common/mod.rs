@@ -0,0 +1,55 @@
+//! A Binance-like exchange shared by the tests running the binary.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+/// The open time of the first requested kline, 2024-01-01 00:00.
+pub const START: i64 = 1704067200000;
+
+/// Serves two minute klines from the `startTime` of each request, after `delay(startTime)`.
+pub fn exchange(delay: fn(i64) -> Duration) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut head = Vec::new();
+                let mut buffer = [0; 1024];
+                while !head.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => head.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                let head = String::from_utf8_lossy(&head);
+                let start = head
+                    .split(['?', '&', ' '])
+                    .find_map(|pair| pair.strip_prefix("startTime=")?.parse::<i64>().ok())
+                    .unwrap_or(START);
+                std::thread::sleep(delay(start));
+                let rows = (0..2)
+                    .map(|i| {
+                        let open_time = start + i * 60_000;
+                        format!(r#"[{open_time},"1","2","0.5","1.5","10",{},"15",3,"4","6","0"]"#, open_time + 59_999)
+                    })
+                    .collect::<Vec<_>>();
+                let body = format!("[{}]", rows.join(","));
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            });
+        }
+    });
+    url
+}
+
+/// A scratch directory for the test `name`, emptied of the previous runs.
+pub fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("download-ticks-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,43 @@
+//! Ctrl-C during a download, the binary saving what it collected so far.
+#![cfg(unix)]
+
+mod common;
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{START, exchange, scratch_dir};
+
+#[test]
+fn ctrl_c_saves_the_klines_downloaded_so_far() {
+    // the first chunk is served right away, the next ones after a minute.
+    let url = exchange(|start| if start == START { Duration::ZERO } else { Duration::from_secs(60) });
+    let dir = scratch_dir("interrupt");
+    let output = dir.join("klines.json");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_download-ticks"))
+        .args(["fetch", "-s", "BTCUSDT", "-i", "m1", "-f", "2024-01-01", "-t", "2024-01-01T00:10:00Z"])
+        .args(["--limit", "2", "--no-validate-symbol", "--base-url", &url, "-o"])
+        .arg(&output)
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the first chunk is downloaded by then, the next ones are stalled.
+    std::thread::sleep(Duration::from_millis(1500));
+    let killed = Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+
+    let started = Instant::now();
+    let status = child.wait().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(10), "the stalled chunks aren't waited for");
+    assert!(status.success(), "{status}");
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+    assert!(stdout.contains("interrupted"), "{stdout}");
+
+    let saved = std::fs::read_to_string(&output).unwrap();
+    assert!(saved.starts_with(&format!("[[{START},")), "{saved}");
+    assert_eq!(saved.matches("],[").count(), 1, "{saved}");
+}
@@ -0,0 +1,40 @@
+//! `--stdout` next to `--output-file`, each sink in its own format.
+
+mod common;
+
+use std::process::Command;
+use std::time::Duration;
+
+use common::{START, exchange, scratch_dir};
+
+#[test]
+fn stdout_is_pretty_while_the_file_is_compact() {
+    let url = exchange(|_| Duration::ZERO);
+    let dir = scratch_dir("stdout");
+    let output = dir.join("klines.json");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_download-ticks"))
+        .args(["fetch", "-s", "BTCUSDT", "-i", "m1", "-f", "2024-01-01", "-t", "2024-01-01T00:01:00Z"])
+        .args([
+            "--no-validate-symbol",
+            "--base-url",
+            &url,
+            "--stdout",
+            "--output-stdout-format",
+            "pretty",
+            "-o",
+        ])
+        .arg(&output)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+
+    let printed = String::from_utf8(run.stdout).unwrap();
+    assert!(printed.starts_with(&format!("[\n  [\n    {START},")), "{printed}");
+    let saved = std::fs::read_to_string(&output).unwrap();
+    assert!(saved.starts_with(&format!("[[{START},")) && !saved.contains('\n'), "{saved}");
+
+    let printed = serde_json::from_str::<serde_json::Value>(&printed).unwrap();
+    assert_eq!(printed, serde_json::from_str::<serde_json::Value>(&saved).unwrap());
+}